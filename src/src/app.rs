@@ -1,13 +1,33 @@
-use crate::{Note, NoteBackend, NoteError, NoteValidationError, PartialNote, Result};
-use log::debug;
-use std::collections::HashSet;
+use crate::graph::{Edge, Graph, Node};
+use crate::references::{self, Reference, Target};
+use crate::{
+    BackendError, Cascade, CascadeAction, CascadeChange, Grant, Groups, Issue, IssueKind,
+    ListQuery, Note, NoteBackend, NoteError, NoteId, NoteValidationError, PartialNote, Permission,
+    Result, Revision, SearchResult, TrashFilter, TrashedNote,
+};
+use chrono::{TimeDelta, Utc};
+use log::{debug, error, info};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// A note, or the section of it under a heading, being expanded by `read_note`
+type Expansion = (NoteId, Option<String>);
+
+/// Most bytes of quoted notes `read_note` inserts into a note. Many references to notes that reference many notes
+/// in turn would grow exponentially otherwise
+const MAX_EXPANDED_SIZE: usize = 1 << 20;
 
 pub struct NoteService {
     pub repo: Box<dyn NoteBackend>,
     pub user: String,
     pub max_name_size: u8,
     pub max_content_size: u16,
-    pub max_note_count: u16,
+    pub max_note_count: u64,
+    /// Days a note stays in the trash before it is purged
+    pub trash_retention_days: u16,
+    /// Groups that can own notes, whose members share ownership
+    pub groups: Groups,
+    /// How many levels of nested references `read_note` expands
+    pub max_reference_depth: u8,
 }
 
 impl NoteService {
@@ -17,7 +37,9 @@ impl NoteService {
         user: String,
         max_name_size: u8,
         max_content_size: u16,
-        max_note_count: u16,
+        max_note_count: u64,
+        trash_retention_days: u16,
+        groups: Groups,
     ) -> Self {
         Self {
             repo,
@@ -25,193 +47,637 @@ impl NoteService {
             max_name_size,
             max_content_size,
             max_note_count,
+            trash_retention_days,
+            groups,
+            max_reference_depth: 3,
         }
     }
 
-    /// List all notes visible to the current user.
+    /// Sets how many levels of nested references `read_note` expands. 1 only expands the references
+    /// in the note itself, and 0 leaves them as they are.
+    #[must_use]
+    pub const fn with_max_reference_depth(mut self, max_reference_depth: u8) -> Self {
+        self.max_reference_depth = max_reference_depth;
+        self
+    }
+
+    /// List notes visible to the current user, filtered, sorted and paginated by the query.
+    /// Notes in the trash are hidden unless the query asks for them.
     ///
     /// # Errors
     ///
     /// Returns an error if the underlying repository fails to retrieve the notes.
-    pub fn list_notes(&self) -> Result<Vec<PartialNote>> {
-        self.repo.list()
+    pub fn list_notes(&self, query: &ListQuery) -> Result<Vec<PartialNote>> {
+        self.repo.list(query)
+    }
+
+    /// Search the name and content of the notes the current user can read for all terms in the query.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::QueryEmpty` if the query is only whitespace.
+    /// - Other repository errors if searching the backend fails.
+    pub fn search_notes(&self, query: &str) -> Result<Vec<SearchResult>> {
+        if query.trim().is_empty() {
+            return Err(NoteValidationError::QueryEmpty.into());
+        }
+
+        // Snippets contain note content, so only show results the user is allowed to read
+        let mut results = Vec::new();
+        for result in self.repo.search(query)? {
+            if result.note.deleted_at.is_none()
+                && self
+                    .authorize(result.note.id, &result.note.owner, Permission::Read)
+                    .is_ok()
+            {
+                results.push(result);
+            }
+        }
+        Ok(results)
     }
 
-    /// Create a new note with the given name and content.
+    /// Create a new note with the given name, content and tags, in a single transaction.
+    /// The note is owned by `owner`, which must be the current user or a group they're a member of,
+    /// or by the current user if `None`.
+    /// Notes in the trash keep their ID and count towards the note limit until they are purged.
     ///
     /// # Errors
     ///
-    /// Returns an error if validation fails or the note could not be saved.
+    /// Returns:
+    /// - `NoteValidationError::NotGroupMember` if the user can't create notes for the given owner.
+    /// - `NoteValidationError::ReferenceNotFound` if a referenced note ID does not exist or is in the trash.
+    /// - `NoteValidationError::ReferenceNameNotFound` or `ReferenceNameAmbiguous` if a referenced name doesn't
+    ///   match exactly one note the user can read.
+    /// - `NoteValidationError::HeadingNotFound` if a referenced heading doesn't exist in the referenced note.
+    /// - Other errors if validation fails or the note could not be saved.
     ///
     /// # Panics
     ///
     /// Panics if no available note ID is found, which should not happen unless there's memory corruption or a logic error.    // Create a new note after validation and reference checks
-    pub fn create_note(&self, name: String, content: String) -> Result<u16> {
+    pub fn create_note(
+        &self,
+        name: String,
+        content: String,
+        tags: &[String],
+        owner: Option<String>,
+    ) -> Result<NoteId> {
         Self::validate_name(&name, self.max_name_size)?;
         Self::validate_content(&content, self.max_content_size)?;
+        Self::validate_tags(tags, self.max_name_size)?;
 
-        // Make sure not too many notes are created
-        let notes = self.repo.list()?;
-        if notes.len() >= self.max_note_count as usize {
-            return Err(NoteValidationError::NoteCountLimit {
-                max: self.max_note_count,
-            }
-            .into());
+        let owner = owner.unwrap_or_else(|| self.user.clone());
+        if !self.is_owner(&owner) {
+            return Err(NoteValidationError::NotGroupMember(owner).into());
         }
 
-        // Find next free ID
-        let used_ids: HashSet<u16> = notes.into_iter().map(|note| note.id).collect();
-        let Some(available_id) = (0..self.max_note_count).find(|id| !used_ids.contains(id)) else {
-            unreachable!();
-        };
-
-        // Make sure all referenced notes actually exist
-        // Search for references in this format: " [[1]] " where 1 is the id of the referenced note
-        for id in self.get_references(&content) {
-            if !used_ids.contains(&id) {
-                return Err(NoteValidationError::ReferenceNotFound(id).into());
+        self.transaction(|| {
+            // Make sure not too many notes are created
+            // Count every stored note, including the ones the backend can't parse, which still take up their ID
+            let ids = self.repo.list_ids()?;
+            if ids.len() as u64 >= self.max_note_count {
+                return Err(NoteValidationError::NoteCountLimit {
+                    max: self.max_note_count,
+                }
+                .into());
             }
 
-            let partial_note: PartialNote = Self::get_partial_note(self, id)?;
-            if partial_note.owner != self.user {
-                return Err(NoteValidationError::PermissionDenied(id).into());
+            // Find next free ID
+            let used_ids: HashSet<NoteId> = ids.into_iter().collect();
+            let Some(available_id) = (0..self.max_note_count).find(|id| !used_ids.contains(id))
+            else {
+                unreachable!();
+            };
+
+            // Make sure all referenced notes actually exist
+            // Search for references in this format: "[[1]]" where 1 is the id of the referenced note,
+            // or "[[Name]]" where Name is the name of a note the user can read
+            let references = self.get_references(&content)?;
+            for &id in &references {
+                if !used_ids.contains(&id) {
+                    return Err(NoteValidationError::ReferenceNotFound(id).into());
+                }
+
+                let partial_note: PartialNote = Self::get_partial_note(self, id)?;
+                self.authorize(id, &partial_note.owner, Permission::Read)?;
+                // Notes in the trash can't be referenced anew
+                if partial_note.deleted_at.is_some() {
+                    return Err(NoteValidationError::ReferenceNotFound(id).into());
+                }
             }
-        }
+            self.validate_headings(available_id, &content)?;
 
-        let note = Note {
-            id: available_id,
-            // The creator is the owner
-            owner: self.user.clone(),
-            name,
-            content,
-        };
+            let now = Utc::now();
+            let note = Note {
+                id: available_id,
+                owner,
+                name,
+                content,
+                created_at: now,
+                updated_at: now,
+                deleted_at: None,
+            };
 
-        self.repo.create(note)
+            let (links, names) = self.resolve_links(&note.owner, &note.content);
+            let name = note.name.clone();
+            let id = self.repo.create(note)?;
+            self.repo.set_tags(id, tags)?;
+            self.repo.set_links(id, &links, &names)?;
+            self.reindex_name_references(&[&name])?;
+            Ok(id)
+        })
     }
 
     /// Reads a full note and expands any references in the content (e.g. `[[1]]` becomes the full text of note #1).
+    /// References inside referenced notes are expanded too, up to `max_reference_depth` levels deep, with each
+    /// level quoted one level deeper. References like `[[1#Heading]]` only expand the section under that Markdown heading.
+    /// References back to a note or section already being expanded, including a note referencing itself,
+    /// are shown as a "circular reference" marker.
+    /// Every reference is authorized again on each read, since the referenced note may have changed owner or been
+    /// replaced by another note with the same ID. References the user can't read are expanded to an
+    /// "access denied" placeholder instead, and references to notes in the trash to a "note not found" placeholder.
+    /// Once `MAX_EXPANDED_SIZE` bytes have been inserted, the remaining references are left as they are written.
     ///
     /// # Errors
     ///
     /// Returns:
-    /// - `NoteValidationError::PermissionDenied` if the user does not own the note or a referenced note.
+    /// - `NoteValidationError::PermissionDenied` if the user can't read the note.
+    /// - `NoteValidationError::NoteTrashed` if the note is in the trash.
     /// - `NoteValidationError::ReferenceNotFound` if a referenced note does not exist.
     /// - Other repository errors if reading from the backend fails.
-    pub fn read_note(&self, id: u16) -> Result<Note> {
+    pub fn read_note(&self, id: NoteId) -> Result<Note> {
         let mut note = self.repo.read(id)?;
 
-        // Only allow owner read access
-        if self.user != note.owner {
-            return Err(NoteValidationError::PermissionDenied(id).into());
+        self.authorize(id, &note.owner, Permission::Read)?;
+        if note.deleted_at.is_some() {
+            return Err(NoteValidationError::NoteTrashed(id).into());
         }
 
-        // Mapping references to note contents: [[1]] -> "Some content"
-        let placeholders = self
-            .get_references(&note.content)
-            .into_iter()
-            .map(|rid| match self.repo.read(rid) {
-                Ok(ref_note) => {
-                    let placeholder = format!("[[{rid}]]");
-                    let expansion = format!(
-                        ">>> #{} {}\n>\n> {}",
-                        ref_note.id,
-                        ref_note.name,
-                        ref_note.content.replace('\n', "\n> ")
-                    );
-                    Ok((placeholder, expansion))
-                }
-                Err(_) => Err(NoteValidationError::ReferenceNotFound(rid).into()),
-            })
-            .collect::<Result<Vec<(String, String)>>>()?;
+        if self.max_reference_depth > 0 {
+            let mut budget = MAX_EXPANDED_SIZE;
+            note.content =
+                self.expand_references(&note.content, &mut vec![(id, None)], &mut budget)?;
+        }
+        Ok(note)
+    }
 
-        // Expanding references: [[1]] -> Note #1's content
-        let expanded = placeholders
-            .into_iter()
-            .fold(note.content, |txt, (ph, exp)| txt.replace(&ph, &exp));
+    /// Lists the notes referencing a note, for showing alongside it. Only notes the current user can read
+    /// are listed, so notes in the trash and notes of other users are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user can't read the note.
+    /// - Other repository errors if the note or the reference index can't be read.
+    pub fn backlinks(&self, id: NoteId) -> Result<Vec<PartialNote>> {
+        let partial_note = self.get_partial_note(id)?;
+        self.authorize(id, &partial_note.owner, Permission::Read)?;
 
-        note.content = expanded;
-        Ok(note)
+        let mut backlinks = Vec::new();
+        for backlink in self.find_backlinks(id)? {
+            // Skip notes that can't be read from the backend, like corrupted ones
+            let Ok(referrer) = self.get_partial_note(backlink) else {
+                continue;
+            };
+            if referrer.deleted_at.is_none()
+                && self
+                    .authorize(referrer.id, &referrer.owner, Permission::Read)
+                    .is_ok()
+            {
+                backlinks.push(referrer);
+            }
+        }
+        Ok(backlinks)
     }
 
-    /// Updates an existing note, replacing its name and content.
+    /// Updates an existing note in a single transaction, replacing its name, content and tags and bumping its modification time.
+    /// The owner of the note is kept as is.
     ///
     /// # Errors
     ///
     /// Returns:
     /// - `NoteValidationError::NameEmpty` or `NameTooLarge` if the new name is invalid.
     /// - `NoteValidationError::ContentEmpty` or `ContentTooLarge` if the new content is invalid.
-    /// - `NoteValidationError::ReferenceNotFound` if a referenced note ID does not exist.
-    /// - `NoteValidationError::PermissionDenied` if the user is not the owner of a referenced note.
+    /// - `NoteValidationError::TagInvalid` or `TagTooLarge` if one of the new tags is invalid.
+    /// - `NoteValidationError::ReferenceNotFound` if a referenced note ID does not exist, or is in the trash and
+    ///   wasn't referenced by the note before.
+    /// - `NoteValidationError::ReferenceNameNotFound` or `ReferenceNameAmbiguous` if a referenced name doesn't
+    ///   match exactly one note the user can read.
+    /// - `NoteValidationError::HeadingNotFound` if a referenced heading doesn't exist in the referenced note.
+    /// - `NoteValidationError::PermissionDenied` if the user can't write the note or can't read a referenced note.
     /// - `NoteValidationError::NoteNotFound` if the note to update doesn't exist.
+    /// - `NoteValidationError::NoteTrashed` if the note to update is in the trash.
     /// - Other backend errors if the repository operation fails.
-    pub fn update_note(&self, note: Note) -> Result<()> {
+    pub fn update_note(&self, mut note: Note, tags: &[String]) -> Result<()> {
         Self::validate_name(&note.name, self.max_name_size)?;
         Self::validate_content(&note.content, self.max_content_size)?;
+        Self::validate_tags(tags, self.max_name_size)?;
 
-        let notes = self.repo.list()?;
-        let used_ids: HashSet<u16> = notes.into_iter().map(|note| note.id).collect();
+        self.transaction(|| {
+            let notes = self.all_notes()?;
 
-        // Make sure all referenced notes actually exist
-        // Search for references in this format: " [[1]] " where 1 is the id of the referenced note
-        for id in self.get_references(&note.content) {
-            if !used_ids.contains(&id) {
-                return Err(NoteValidationError::ReferenceNotFound(id).into());
+            // Make sure the note we are updating actually exist, and the user is allowed to change it
+            let Some(stored) = notes.iter().find(|partial_note| partial_note.id == note.id) else {
+                return Err(NoteValidationError::NoteNotFound(note.id).into());
+            };
+            self.authorize(stored.id, &stored.owner, Permission::Write)?;
+            if stored.deleted_at.is_some() {
+                return Err(NoteValidationError::NoteTrashed(note.id).into());
             }
+            // Users with write access can't hand the note over to someone else
+            note.owner.clone_from(&stored.owner);
+
+            let used_ids: HashSet<NoteId> = notes.iter().map(|note| note.id).collect();
 
-            // Make sure the user is allowed to read the referenced note
-            let partial_note: PartialNote = Self::get_partial_note(self, id)?;
-            if partial_note.owner != self.user {
-                return Err(NoteValidationError::PermissionDenied(id).into());
+            // Make sure all referenced notes actually exist
+            // Search for references in this format: "[[1]]" where 1 is the id of the referenced note,
+            // or "[[Name]]" where Name is the name of a note the user can read
+            let references = self.get_references(&note.content)?;
+            for &id in &references {
+                if !used_ids.contains(&id) {
+                    // References to purged notes are kept too, if they were made before
+                    if self.repo.backlinks(id)?.contains(&note.id) {
+                        continue;
+                    }
+                    return Err(NoteValidationError::ReferenceNotFound(id).into());
+                }
+
+                // Make sure the user is allowed to read the referenced note
+                let partial_note: PartialNote = Self::get_partial_note(self, id)?;
+                self.authorize(id, &partial_note.owner, Permission::Read)?;
+                // Notes in the trash can't be referenced anew, but references made before they were trashed are kept
+                if partial_note.deleted_at.is_some() && !self.repo.backlinks(id)?.contains(&note.id)
+                {
+                    return Err(NoteValidationError::ReferenceNotFound(id).into());
+                }
             }
-        }
+            self.validate_headings(note.id, &note.content)?;
 
-        // Make sure the note we are updating actually exist
-        if used_ids.contains(&note.id) {
-            self.repo.update(note)
-        } else {
-            Err(NoteValidationError::NoteNotFound(note.id).into())
-        }
+            note.updated_at = Utc::now();
+            let id = note.id;
+            let (links, names) = self.resolve_links(&note.owner, &note.content);
+            let renamed =
+                (stored.name != note.name).then(|| [stored.name.clone(), note.name.clone()]);
+            self.repo.update(note)?;
+            self.repo.set_tags(id, tags)?;
+            self.repo.set_links(id, &links, &names)?;
+            match renamed {
+                Some([old, new]) => self.reindex_name_references(&[&old, &new]),
+                None => Ok(()),
+            }
+        })
     }
 
-    /// Deletes a note by ID, but only if no other notes reference it.
+    /// Moves a note to the trash in a single transaction, but only if no other notes its owner controls reference it.
+    /// Notes in the trash still count as references until they are purged. See `delete_note_cascading`
+    /// for deleting a note along with the notes referencing it.
     ///
     /// # Errors
     ///
     /// Returns:
-    /// - `NoteValidationError::NoteIsReferenced` if other notes reference the note being deleted.
-    /// - Backend errors if the note cannot be read or deleted.
-    pub fn delete_note(&self, id: u16) -> Result<()> {
-        // Check if any other note references this note (expensive)
-        // and do not stop at the first backlink, find all of them
-        let mut backlinks: Vec<u16> = Vec::new();
-        for partial_note in self.list_notes()? {
-            // Do not prevent deletion if note refers to itself
-            if partial_note.id == id {
-                // While we're here: Check if user is the owner of the note
-                // Make sure they can't delete a note they don't own
-                if partial_note.owner != self.user {
-                    return Err(NoteValidationError::PermissionDenied(partial_note.id).into());
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - `NoteValidationError::NoteTrashed` if the note is already in the trash.
+    /// - `NoteValidationError::NoteIsReferenced` if other notes of its owner reference the note being deleted.
+    /// - Backend errors if the note cannot be read or moved to the trash.
+    pub fn delete_note(&self, id: NoteId) -> Result<()> {
+        self.transaction(|| {
+            let partial_note = self.get_partial_note(id)?;
+            // Make sure they can't delete a note they don't own
+            self.authorize(id, &partial_note.owner, Permission::Manage)?;
+            if partial_note.deleted_at.is_some() {
+                return Err(NoteValidationError::NoteTrashed(id).into());
+            }
+
+            let backlinks = self.find_blocking_backlinks(id, &partial_note.owner)?;
+            if !backlinks.is_empty() {
+                return Err(NoteError::Validation(
+                    NoteValidationError::NoteIsReferenced(backlinks),
+                ));
+            }
+            self.repo.set_deleted(id, Some(Utc::now()))?;
+            self.reindex_name_references(&[&partial_note.name])
+        })
+    }
+
+    /// Moves a note to the trash in a single transaction like `delete_note`, but deals with the notes referencing it
+    /// that its owner controls instead of refusing. With `Cascade::Delete`, those are moved to the trash too, while
+    /// notes referencing them are left as they are. With `Cascade::Tombstone`, references to the note are replaced by
+    /// a tombstone marker like `[deleted: #3]`, keeping the previous version of each changed note as a revision.
+    /// Nothing is changed if the user can't manage any of those notes.
+    ///
+    /// # Returns
+    ///
+    /// Every change made, starting with the deleted note.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - `NoteValidationError::NoteTrashed` if the note is already in the trash.
+    /// - `NoteValidationError::ReferencedByOthers` if the user can't manage a note that would change.
+    /// - Backend errors if the notes cannot be read or changed.
+    pub fn delete_note_cascading(
+        &self,
+        id: NoteId,
+        cascade: Cascade,
+    ) -> Result<Vec<CascadeChange>> {
+        self.transaction(|| {
+            let partial_note = self.get_partial_note(id)?;
+            self.authorize(id, &partial_note.owner, Permission::Manage)?;
+            if partial_note.deleted_at.is_some() {
+                return Err(NoteValidationError::NoteTrashed(id).into());
+            }
+
+            let referrers: Vec<PartialNote> = self
+                .find_blocking_backlinks(id, &partial_note.owner)?
+                .into_iter()
+                .map(|backlink| self.get_partial_note(backlink))
+                .collect::<Result<_>>()?;
+            let mut foreign = Vec::new();
+            for referrer in &referrers {
+                match self.authorize(referrer.id, &referrer.owner, Permission::Manage) {
+                    Ok(()) => {}
+                    Err(NoteError::Validation(NoteValidationError::PermissionDenied(_))) => {
+                        foreign.push(referrer.id);
+                    }
+                    Err(e) => return Err(e),
                 }
-                continue;
+            }
+            if !foreign.is_empty() {
+                return Err(NoteValidationError::ReferencedByOthers(foreign).into());
             }
 
-            // Read content and find all references
-            // Save ID to Vec if it contains a backlink
-            // to the note we're trying to delete
-            let content = self.repo.read(id)?.content;
-            let references = self.get_references(&content);
-            if references.contains(&id) {
-                backlinks.push(partial_note.id);
+            let now = Utc::now();
+            let mut changes = vec![CascadeChange {
+                note_id: id,
+                name: partial_note.name,
+                action: CascadeAction::Trashed,
+            }];
+            for referrer in referrers {
+                let action = match cascade {
+                    // Notes already in the trash stay as they are
+                    Cascade::Delete if referrer.deleted_at.is_some() => continue,
+                    Cascade::Delete => {
+                        self.repo.set_deleted(referrer.id, Some(now))?;
+                        CascadeAction::Trashed
+                    }
+                    Cascade::Tombstone => {
+                        let note = self.repo.read(referrer.id)?;
+                        let replaced: Vec<Reference> = references::parse(&note.content)
+                            .into_iter()
+                            // Resolve names like the reference index does, on behalf of the owner
+                            .filter(|reference| {
+                                self.resolve_target_as(&note.owner, &reference.target)
+                                    .is_ok_and(|target| target == id)
+                            })
+                            .collect();
+                        self.rewrite_references(note, &replaced, |reference| {
+                            format!("[deleted: {}]", reference.text())
+                        })?;
+                        CascadeAction::Tombstoned {
+                            references: replaced.len(),
+                        }
+                    }
+                };
+                changes.push(CascadeChange {
+                    note_id: referrer.id,
+                    name: referrer.name,
+                    action,
+                });
             }
-        }
 
-        let num_backlinks = backlinks.len();
-        match num_backlinks {
-            0 => self.repo.delete(id),
-            _ => Err(NoteError::Validation(
-                NoteValidationError::NoteIsReferenced(backlinks),
-            )),
+            self.repo.set_deleted(id, Some(now))?;
+            let trashed: Vec<&str> = changes
+                .iter()
+                .filter(|change| change.action == CascadeAction::Trashed)
+                .map(|change| change.name.as_str())
+                .collect();
+            self.reindex_name_references(&trashed)?;
+            Ok(changes)
+        })
+    }
+
+    /// Lists the current user's notes in the trash, with the time each will be purged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails to retrieve the notes.
+    pub fn list_trash(&self) -> Result<Vec<TrashedNote>> {
+        let query = ListQuery {
+            trash: TrashFilter::Only,
+            ..ListQuery::default()
+        };
+        Ok(self
+            .repo
+            .list(&query)?
+            .into_iter()
+            .filter(|note| {
+                self.authorize(note.id, &note.owner, Permission::Manage)
+                    .is_ok()
+            })
+            .filter_map(|note| {
+                let deleted_at = note.deleted_at?;
+                Some(TrashedNote {
+                    note,
+                    deleted_at,
+                    purged_at: deleted_at + self.trash_retention(),
+                })
+            })
+            .collect())
+    }
+
+    /// Restores a note owned by the current user from the trash.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - `NoteValidationError::NoteNotTrashed` if the note isn't in the trash.
+    /// - Backend errors if the note cannot be read or restored.
+    pub fn restore_note(&self, id: NoteId) -> Result<()> {
+        self.transaction(|| {
+            let partial_note = self.get_trashed_note(id)?;
+            self.repo.set_deleted(id, None)?;
+            self.reindex_name_references(&[&partial_note.name])
+        })
+    }
+
+    /// Permanently deletes a note owned by the current user from the trash,
+    /// but only if no other notes its owner controls reference it.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - `NoteValidationError::NoteNotTrashed` if the note isn't in the trash.
+    /// - `NoteValidationError::NoteIsReferenced` if other notes of its owner reference the note being purged.
+    /// - Backend errors if the note cannot be read or deleted.
+    pub fn purge_note(&self, id: NoteId) -> Result<()> {
+        self.transaction(|| {
+            let partial_note = self.get_trashed_note(id)?;
+
+            let backlinks = self.find_blocking_backlinks(id, &partial_note.owner)?;
+            if !backlinks.is_empty() {
+                return Err(NoteError::Validation(
+                    NoteValidationError::NoteIsReferenced(backlinks),
+                ));
+            }
+            self.repo.delete(id)
+        })
+    }
+
+    /// Permanently deletes every note, of any user, that has been in the trash for longer than the retention period.
+    /// Notes still referenced by other notes of their owner are kept until the references are gone.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the purged notes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trash cannot be listed or a note cannot be deleted.
+    pub fn purge_expired_notes(&self) -> Result<Vec<NoteId>> {
+        let query = ListQuery {
+            trash: TrashFilter::Only,
+            ..ListQuery::default()
+        };
+        let now = Utc::now();
+
+        self.transaction(|| {
+            let mut purged = Vec::new();
+            for note in self.repo.list(&query)? {
+                if note
+                    .deleted_at
+                    .is_none_or(|deleted_at| deleted_at + self.trash_retention() > now)
+                {
+                    continue;
+                }
+
+                let backlinks = self.find_blocking_backlinks(note.id, &note.owner)?;
+                if backlinks.is_empty() {
+                    self.repo.delete(note.id)?;
+                    purged.push(note.id);
+                } else {
+                    info!(
+                        "Keeping expired note #{} in the trash, it is referenced by: {backlinks:?}",
+                        note.id
+                    );
+                }
+            }
+            Ok(purged)
+        })
+    }
+
+    /// Fetches the tags attached to a note the current user can read.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user can't read the note.
+    /// - Other repository errors if reading from the backend fails.
+    pub fn read_tags(&self, id: NoteId) -> Result<Vec<String>> {
+        let partial_note = self.get_partial_note(id)?;
+        self.authorize(id, &partial_note.owner, Permission::Read)?;
+        self.repo.read_tags(id)
+    }
+
+    /// Lists the previous versions of a note the current user can read, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user can't read the note.
+    /// - Other repository errors if reading from the backend fails.
+    pub fn list_revisions(&self, id: NoteId) -> Result<Vec<Revision>> {
+        let partial_note = self.get_partial_note(id)?;
+        self.authorize(id, &partial_note.owner, Permission::Read)?;
+        self.repo.list_revisions(id)
+    }
+
+    /// Reads a previous version of a note the current user can read, without expanding references.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user can't read the note.
+    /// - Other repository errors if the revision doesn't exist or reading from the backend fails.
+    pub fn read_revision(&self, id: NoteId, revision: u32) -> Result<Revision> {
+        let partial_note = self.get_partial_note(id)?;
+        self.authorize(id, &partial_note.owner, Permission::Read)?;
+        self.repo.read_revision(id, revision)
+    }
+
+    /// Restores the name and content of a note from a previous version. This is a regular update,
+    /// so the version being replaced is kept as a new revision and the tags are left as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user can't write the note or read a note referenced by the revision.
+    /// - `NoteValidationError::ReferenceNotFound` if a note referenced by the revision no longer exists.
+    /// - Other validation or repository errors, like for `update_note`.
+    pub fn restore_revision(&self, id: NoteId, revision: u32) -> Result<()> {
+        let old = self.read_revision(id, revision)?;
+        let tags = self.repo.read_tags(id)?;
+        let mut note = self.repo.read(id)?;
+
+        note.name = old.name;
+        note.content = old.content;
+        self.update_note(note, &tags)
+    }
+
+    /// Lists the users a note owned by the current user is shared with.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - Other repository errors if reading from the backend fails.
+    pub fn list_grants(&self, id: NoteId) -> Result<Vec<Grant>> {
+        let partial_note = self.get_partial_note(id)?;
+        self.authorize(id, &partial_note.owner, Permission::Manage)?;
+        self.repo.list_grants(id)
+    }
+
+    /// Shares a note owned by the current user with another user, replacing any access they already had.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::UserEmpty` if the username is only whitespace.
+    /// - `NoteValidationError::PermissionNotGrantable` if the permission is `Permission::Manage`.
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - Other repository errors if the grant could not be stored.
+    pub fn share_note(&self, id: NoteId, user: &str, permission: Permission) -> Result<()> {
+        if user.trim().is_empty() {
+            return Err(NoteValidationError::UserEmpty.into());
         }
+        if permission == Permission::Manage {
+            return Err(NoteValidationError::PermissionNotGrantable(permission).into());
+        }
+
+        self.transaction(|| {
+            let partial_note = self.get_partial_note(id)?;
+            self.authorize(id, &partial_note.owner, Permission::Manage)?;
+            self.repo.set_grant(id, user, permission)?;
+            self.reindex_name_references(&[&partial_note.name])
+        })
+    }
+
+    /// Takes away the access another user was given to a note owned by the current user.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - Other repository errors if the user has no access granted or the grant could not be removed.
+    pub fn unshare_note(&self, id: NoteId, user: &str) -> Result<()> {
+        self.transaction(|| {
+            let partial_note = self.get_partial_note(id)?;
+            self.authorize(id, &partial_note.owner, Permission::Manage)?;
+            self.repo.remove_grant(id, user)?;
+            self.reindex_name_references(&[&partial_note.name])
+        })
     }
 
     /// Creates a special "flag" note owned by a specialist group of elite hackers
@@ -225,7 +691,7 @@ impl NoteService {
     /// # Panics
     ///
     /// Panics if no available note ID is found, which should be logically impossible unless data corruption occurred.
-    pub fn create_flag_note(&self) -> Result<u16> {
+    pub fn create_flag_note(&self) -> Result<NoteId> {
         use std::env;
 
         let flag = env::var("FLAG").unwrap_or_else(|_| {
@@ -233,34 +699,298 @@ impl NoteService {
             "NNS{placeholder}".to_string()
         });
 
-        // Make sure not too many notes are created
-        let notes = self.repo.list()?;
-        if notes.len() >= self.max_note_count as usize {
-            return Err(NoteValidationError::NoteCountLimit {
-                max: self.max_note_count,
+        self.transaction(|| {
+            // Make sure not too many notes are created
+            // Count every stored note, including the ones the backend can't parse, which still take up their ID
+            let ids = self.repo.list_ids()?;
+            if ids.len() as u64 >= self.max_note_count {
+                return Err(NoteValidationError::NoteCountLimit {
+                    max: self.max_note_count,
+                }
+                .into());
             }
-            .into());
-        }
 
-        // Find next free ID
-        let used_ids: HashSet<u16> = notes.into_iter().map(|note| note.id).collect();
-        let available_id = (0..self.max_note_count)
-            .find(|id| !used_ids.contains(id))
-            .expect("Available ID not found despite more space for more notes");
+            // Find next free ID
+            let used_ids: HashSet<NoteId> = ids.into_iter().collect();
+            let available_id = (0..self.max_note_count)
+                .find(|id| !used_ids.contains(id))
+                .expect("Available ID not found despite more space for more notes");
 
-        // The creator is the owner
-        let note = Note {
-            id: available_id,
-            owner: "Norske Nøkkelsnikere".to_string(),
-            name: "flag".to_string(),
-            content: flag,
-        };
+            // The creator is the owner
+            let now = Utc::now();
+            let note = Note {
+                id: available_id,
+                owner: "Norske Nøkkelsnikere".to_string(),
+                name: "flag".to_string(),
+                content: flag,
+                created_at: now,
+                updated_at: now,
+                deleted_at: None,
+            };
 
-        self.repo.create(note)
+            self.repo.create(note)
+        })
     }
 
     // --- small helpers ---
 
+    /// Runs an operation inside a backend transaction, so the checks it makes can't race with
+    /// other writers. Commits if the operation succeeds and rolls back if it fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the operation, or a backend error if the transaction can't be started or committed.
+    fn transaction<T>(&self, operation: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.repo.begin()?;
+
+        let result = operation().and_then(|value| self.repo.commit().map(|()| value));
+        if result.is_err()
+            && let Err(e) = self.repo.rollback()
+        {
+            error!("Failed rolling back transaction: {e}");
+        }
+        result
+    }
+
+    /// Fills the reference index from the content of every note, if the backend was created before the index existed.
+    ///
+    /// # Returns
+    ///
+    /// Whether the index had to be filled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a note can't be read or its references can't be stored.
+    pub fn index_links(&self) -> Result<bool> {
+        if self.repo.links_indexed()? {
+            return Ok(false);
+        }
+
+        self.transaction(|| {
+            self.repo.mark_links_indexed()?;
+            for partial_note in self.all_notes()? {
+                match self.repo.read(partial_note.id) {
+                    Ok(note) => {
+                        let (links, names) = self.resolve_links(&note.owner, &note.content);
+                        self.repo.set_links(note.id, &links, &names)?;
+                    }
+                    // Corrupted notes have no references worth indexing
+                    Err(e) => error!(
+                        "Skipping note #{} while indexing references: {e}",
+                        partial_note.id
+                    ),
+                }
+            }
+            Ok(())
+        })?;
+        Ok(true)
+    }
+
+    /// Builds the graph of references between the notes the current user can read, outside the trash.
+    /// References are resolved like `read_note` does, and references to notes the user can't read are left out.
+    /// With a `root`, the graph only has the notes reachable from it through references.
+    /// Notes the backend can't read, like corrupted ones, are logged and left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user can't read the root note.
+    /// - `NoteValidationError::NoteTrashed` if the root note is in the trash.
+    /// - Other repository errors if the root note or the list of notes can't be read.
+    pub fn reference_graph(&self, root: Option<NoteId>) -> Result<Graph> {
+        if let Some(id) = root {
+            let note = self.repo.read(id)?;
+            self.authorize(id, &note.owner, Permission::Read)?;
+            if note.deleted_at.is_some() {
+                return Err(NoteValidationError::NoteTrashed(id).into());
+            }
+        }
+
+        let mut readable = BTreeMap::new();
+        for partial_note in self.repo.list(&ListQuery::default())? {
+            if self
+                .authorize(partial_note.id, &partial_note.owner, Permission::Read)
+                .is_ok()
+            {
+                readable.insert(partial_note.id, partial_note);
+            }
+        }
+
+        // Leave out notes that can't be read, like corrupted ones, the way `list` leaves out files it can't parse
+        let mut contents = BTreeMap::new();
+        readable.retain(|&id, _| match self.repo.read(id) {
+            Ok(note) => {
+                contents.insert(id, note.content);
+                true
+            }
+            Err(e) => {
+                error!("Leaving note #{id} out of the reference graph: {e}");
+                false
+            }
+        });
+
+        let mut links = BTreeMap::new();
+        for (&id, content) in &contents {
+            let targets: BTreeSet<NoteId> = references::parse(content)
+                .iter()
+                .filter_map(|reference| self.resolve_target(&reference.target).ok())
+                .filter(|target| readable.contains_key(target))
+                .collect();
+            links.insert(id, targets);
+        }
+
+        let included = root.map_or_else(
+            || readable.keys().copied().collect(),
+            |id| Self::reachable(&links, id),
+        );
+
+        let mut graph = Graph::default();
+        for id in included {
+            let partial_note = &readable[&id];
+            graph.nodes.push(Node {
+                id,
+                name: partial_note.name.clone(),
+                owner: partial_note.owner.clone(),
+            });
+            graph
+                .edges
+                .extend(links[&id].iter().map(|&target| Edge { source: id, target }));
+        }
+        Ok(graph)
+    }
+
+    /// Checks every stored note, including the ones the backend can't parse, for corrupted records, references to
+    /// notes or names that don't exist, and references to notes the owner of the referencing note can't read.
+    /// Notes in the trash are checked too, since they can be restored.
+    ///
+    /// With `fix`, corrupted notes are quarantined, and bad references are replaced by their display text or
+    /// target, keeping the previous version of the note as a revision. Only notes the current user can manage are
+    /// fixed, except for notes so corrupted that not even their owner can be read, which nobody could manage. Those
+    /// are quarantined by any user allowed to change the stored notes. Each note is fixed in its own transaction,
+    /// and notes that can't be fixed are logged and left as they are.
+    ///
+    /// The problems found in notes the current user can't read are reported without details, since those would
+    /// show parts of the note.
+    ///
+    /// # Returns
+    ///
+    /// The problems found, ordered by note ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notes can't be listed, or the notes or grants they reference can't be looked up.
+    pub fn check(&self, fix: bool) -> Result<Vec<Issue>> {
+        let mut issues = Vec::new();
+        for id in self.repo.list_ids()? {
+            let note =
+                match self.repo.read(id) {
+                    Ok(note) => note,
+                    Err(e) => {
+                        let fixed =
+                            fix && self.repo.read_partial(id).ok().is_none_or(|partial_note| {
+                                self.may_manage(id, &partial_note.owner)
+                            }) && self
+                                .transaction(|| self.repo.quarantine(id))
+                                .inspect_err(|e| error!("Failed quarantining note #{id}: {e}"))
+                                .is_ok();
+                        issues.push(Issue {
+                            note_id: id,
+                            kind: IssueKind::Corrupted,
+                            detail: e.to_string(),
+                            fixed,
+                        });
+                        continue;
+                    }
+                };
+
+            let readable = self.authorize(id, &note.owner, Permission::Read).is_ok();
+            let mut bad_references = Vec::new();
+            let mut note_issues = Vec::new();
+            for reference in references::parse(&note.content) {
+                if let Some((kind, detail)) = self.check_reference(&note, &reference)? {
+                    bad_references.push(reference);
+                    note_issues.push(Issue {
+                        note_id: id,
+                        kind,
+                        detail: if readable {
+                            detail
+                        } else {
+                            "[details hidden, you can't read this note]".to_string()
+                        },
+                        fixed: false,
+                    });
+                }
+            }
+
+            if fix && !bad_references.is_empty() && self.may_manage(id, &note.owner) {
+                match self
+                    .transaction(|| self.rewrite_references(note, &bad_references, Reference::text))
+                {
+                    Ok(()) => note_issues.iter_mut().for_each(|issue| issue.fixed = true),
+                    Err(e) => error!("Failed fixing the references of note #{id}: {e}"),
+                }
+            }
+            issues.extend(note_issues);
+        }
+        Ok(issues)
+    }
+
+    /// Lists the groups the current user is a member of, and can create notes for.
+    #[must_use]
+    pub fn user_groups(&self) -> Vec<String> {
+        self.groups.groups_of(&self.user)
+    }
+
+    /// Checks that the current user has at least the given permission on a note.
+    /// The owner, or every member if it is owned by a group, has every permission.
+    /// Other users only have what they were granted, and never `Permission::Manage`.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user lacks the permission.
+    /// - Other repository errors if the grants of the note can't be read.
+    fn authorize(&self, id: NoteId, owner: &str, permission: Permission) -> Result<()> {
+        self.authorize_as(&self.user, id, owner, permission)
+    }
+
+    /// Whether the current user can manage a note, for changes only made to notes the user can manage
+    fn may_manage(&self, id: NoteId, owner: &str) -> bool {
+        self.authorize(id, owner, Permission::Manage).is_ok()
+    }
+
+    /// Checks that a given user, or group, has at least the given permission on a note, like `authorize` does
+    /// for the current user.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user lacks the permission.
+    /// - Other repository errors if the grants of the note can't be read.
+    fn authorize_as(
+        &self,
+        user: &str,
+        id: NoteId,
+        owner: &str,
+        permission: Permission,
+    ) -> Result<()> {
+        if self.is_owner_as(user, owner) {
+            return Ok(());
+        }
+
+        let granted = permission != Permission::Manage
+            && self
+                .repo
+                .list_grants(id)?
+                .iter()
+                .any(|grant| grant.user == user && grant.permission >= permission);
+        if granted {
+            Ok(())
+        } else {
+            Err(NoteValidationError::PermissionDenied(id).into())
+        }
+    }
+
     /// Validates a note name against length and emptiness.
     ///
     /// # Errors
@@ -303,22 +1033,493 @@ impl NoteService {
         }
     }
 
-    /// Extracts referenced note IDs in the form of `[[id]]` from the given string.
+    /// Validates tags against length and allowed characters.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::TagInvalid` if a tag is empty or contains whitespace or commas.
+    /// - `NoteValidationError::TagTooLarge` if a tag exceeds the given length.
+    pub fn validate_tags(tags: &[String], max: u8) -> Result<()> {
+        for tag in tags {
+            if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+                return Err(NoteValidationError::TagInvalid(tag.clone()).into());
+            }
+            if tag.len() > max as usize {
+                return Err(NoteValidationError::TagTooLarge {
+                    max,
+                    got: tag.len(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits user input into tags separated by whitespace or commas, dropping duplicates.
     ///
     /// # Returns
     ///
-    /// A vector of note IDs found inside double brackets.
-    #[allow(clippy::unused_self)]
-    fn get_references(&self, s: &str) -> Vec<u16> {
-        s.split_whitespace()
-            .filter_map(|tok| {
-                if tok.starts_with("[[") && tok.ends_with("]]") {
-                    tok[2..tok.len() - 2].parse().ok()
-                } else {
-                    None
+    /// The tags in the order they were first given.
+    #[must_use]
+    pub fn parse_tags(input: &str) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in input.split(|c: char| c.is_whitespace() || c == ',') {
+            if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+        tags
+    }
+
+    /// Lists every note in the backend, including the ones in the trash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails to retrieve the notes.
+    fn all_notes(&self) -> Result<Vec<PartialNote>> {
+        self.repo.list(&ListQuery {
+            trash: TrashFilter::Include,
+            ..ListQuery::default()
+        })
+    }
+
+    /// Finds all other notes referencing a note in the reference index, including notes in the trash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reference index cannot be read.
+    fn find_backlinks(&self, id: NoteId) -> Result<Vec<NoteId>> {
+        let mut backlinks = self.repo.backlinks(id)?;
+        // Do not count a note referring to itself
+        backlinks.retain(|&backlink| backlink != id);
+        Ok(backlinks)
+    }
+
+    /// Finds the other notes whose references keep a note from being deleted or purged: the ones its owner controls,
+    /// directly or through a group, including notes in the trash. Users who can write to a note of someone else
+    /// can add references to it, but those don't stop the referenced note from being deleted, and are left dangling.
+    /// Referencing notes that can't be read are counted, as there's no telling who owns them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reference index cannot be read.
+    fn find_blocking_backlinks(&self, id: NoteId, owner: &str) -> Result<Vec<NoteId>> {
+        let mut backlinks = self.find_backlinks(id)?;
+        backlinks.retain(|&backlink| {
+            self.get_partial_note(backlink)
+                .ok()
+                .is_none_or(|referrer| self.is_owner_as(owner, &referrer.owner))
+        });
+        Ok(backlinks)
+    }
+
+    /// Expands the references in the content of a note, recursing into the referenced notes.
+    ///
+    /// # Parameters
+    ///
+    /// - `content`: Content of the last note or section in `path`
+    /// - `path`: Notes and sections being expanded, from the note being read down to the one `content` belongs to
+    /// - `budget`: Bytes that may still be inserted by expansions
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::ReferenceNotFound` if a referenced note does not exist.
+    /// - Other repository errors if the grants of a referenced note can't be read.
+    fn expand_references(
+        &self,
+        content: &str,
+        path: &mut Vec<Expansion>,
+        budget: &mut usize,
+    ) -> Result<String> {
+        // Replace each reference by its span, so text inserted by one expansion is never parsed again
+        let mut expanded = String::with_capacity(content.len());
+        let mut last = 0;
+        for reference in references::parse(content) {
+            expanded.push_str(&content[last..reference.span.start]);
+            match self.expand_reference(&reference, path, budget)? {
+                Some(expansion) => expanded.push_str(&expansion),
+                None => expanded.push_str(&content[reference.span.clone()]),
+            }
+            last = reference.span.end;
+        }
+        expanded.push_str(&content[last..]);
+        Ok(expanded)
+    }
+
+    /// Renders a single reference as a quote of the referenced note or section, titled with its display text or name,
+    /// or as a marker if it is circular, the user can't read it, it is in the trash or the heading is gone. References in the quoted note are expanded while `path` is shallower
+    /// than `max_reference_depth`, and left as they are below that.
+    /// Names are resolved again on every read, since notes may have been renamed since, and are shown as a marker if they
+    /// no longer match exactly one note.
+    ///
+    /// # Returns
+    ///
+    /// The rendered reference, or `None` if quoting the note would use up more than the remaining `budget`, in which
+    /// case the reference is left as it is written. The budget is charged with the size of the whole quote,
+    /// including the quotes nested in it.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::ReferenceNotFound` if a referenced note does not exist.
+    /// - Other repository errors if the grants of a referenced note can't be read.
+    fn expand_reference(
+        &self,
+        reference: &Reference,
+        path: &mut Vec<Expansion>,
+        budget: &mut usize,
+    ) -> Result<Option<String>> {
+        let rid = match self.resolve_target(&reference.target) {
+            Ok(rid) => rid,
+            Err(NoteError::Validation(NoteValidationError::ReferenceNameNotFound(_))) => {
+                return Ok(Some(format!(
+                    ">>> {} [no note with this name]",
+                    reference.target
+                )));
+            }
+            Err(NoteError::Validation(NoteValidationError::ReferenceNameAmbiguous { .. })) => {
+                return Ok(Some(format!(">>> {} [ambiguous name]", reference.target)));
+            }
+            Err(e) => return Err(e),
+        };
+        let expansion = (rid, reference.heading.clone());
+        if path.contains(&expansion) {
+            return Ok(Some(format!(">>> #{rid} [circular reference]")));
+        }
+
+        let ref_note = match self.repo.read(rid) {
+            Ok(ref_note) => ref_note,
+            // Notes can be purged while notes their owner doesn't control still reference them
+            Err(NoteError::Backend(BackendError::NoteNotFound(_))) => {
+                return Ok(Some(format!(">>> #{rid} [note not found]")));
+            }
+            Err(_) => return Err(NoteValidationError::ReferenceNotFound(rid).into()),
+        };
+        match self.authorize(rid, &ref_note.owner, Permission::Read) {
+            Ok(()) => {}
+            Err(NoteError::Validation(NoteValidationError::PermissionDenied(_))) => {
+                return Ok(Some(format!(">>> #{rid} [access denied]")));
+            }
+            Err(e) => return Err(e),
+        }
+        if ref_note.deleted_at.is_some() {
+            return Ok(Some(format!(">>> #{rid} [note not found]")));
+        }
+
+        let (title, section) = match &reference.heading {
+            Some(heading) => {
+                let Some(section) = references::section(&ref_note.content, heading) else {
+                    return Ok(Some(format!(">>> #{rid} {heading} [heading not found]")));
+                };
+                (format!("{}#{heading}", ref_note.name), section)
+            }
+            None => (ref_note.name.clone(), ref_note.content.as_str()),
+        };
+
+        if section.len() > *budget {
+            return Ok(None);
+        }
+        let before = *budget;
+        let content = if path.len() < usize::from(self.max_reference_depth) {
+            path.push(expansion);
+            let content = self.expand_references(section, path, budget);
+            path.pop();
+            content?
+        } else {
+            section.to_string()
+        };
+        let rendered = format!(
+            ">>> #{} {}\n>\n> {}",
+            ref_note.id,
+            reference.display.as_ref().unwrap_or(&title),
+            content.replace('\n', "\n> ")
+        );
+        // The quotes inside were charged while rendering, but are only inserted as part of this one
+        if rendered.len() > before {
+            *budget = before;
+            return Ok(None);
+        }
+        *budget = before - rendered.len();
+        Ok(Some(rendered))
+    }
+
+    /// Checks a single reference of a note on behalf of its owner, for `check`.
+    ///
+    /// # Returns
+    ///
+    /// The kind of problem with the reference and a description, or `None` if the owner can read the referenced note.
+    ///
+    /// # Errors
+    ///
+    /// Returns repository errors if notes can't be looked up by name, or the grants of the referenced note can't be read.
+    fn check_reference(
+        &self,
+        note: &Note,
+        reference: &Reference,
+    ) -> Result<Option<(IssueKind, String)>> {
+        let token = &note.content[reference.span.clone()];
+        let rid = match self.resolve_target_as(&note.owner, &reference.target) {
+            Ok(rid) => rid,
+            Err(NoteError::Validation(
+                e @ (NoteValidationError::ReferenceNameNotFound(_)
+                | NoteValidationError::ReferenceNameAmbiguous { .. }),
+            )) => return Ok(Some((IssueKind::BrokenReference, format!("{token}: {e}")))),
+            Err(e) => return Err(e),
+        };
+
+        let Ok(ref_note) = self.repo.read(rid) else {
+            let e = NoteValidationError::ReferenceNotFound(rid);
+            return Ok(Some((IssueKind::BrokenReference, format!("{token}: {e}"))));
+        };
+        match self.authorize_as(&note.owner, rid, &ref_note.owner, Permission::Read) {
+            Ok(()) => Ok(reference
+                .heading
+                .as_ref()
+                .filter(|heading| references::section(&ref_note.content, heading).is_none())
+                .map(|heading| {
+                    let e = NoteValidationError::HeadingNotFound {
+                        id: rid,
+                        heading: heading.clone(),
+                    };
+                    (IssueKind::BrokenReference, format!("{token}: {e}"))
+                })),
+            Err(NoteError::Validation(NoteValidationError::PermissionDenied(_))) => Ok(Some((
+                IssueKind::CrossingReference,
+                format!(
+                    "{token}: '{}' can't read note #{rid} owned by '{}'",
+                    note.owner, ref_note.owner
+                ),
+            ))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces references in a note by text made from each reference, and updates the reference index.
+    /// The previous version of the note is kept as a revision.
+    ///
+    /// # Parameters
+    ///
+    /// - `note`: The note, as stored
+    /// - `replaced`: References parsed from the content of the note, in order of appearance
+    /// - `replacement`: Makes the text replacing a reference
+    ///
+    /// # Errors
+    ///
+    /// Returns repository errors if the note or its references can't be saved.
+    fn rewrite_references(
+        &self,
+        note: Note,
+        replaced: &[Reference],
+        replacement: impl Fn(&Reference) -> String,
+    ) -> Result<()> {
+        let mut content = String::with_capacity(note.content.len());
+        let mut last = 0;
+        for reference in replaced {
+            content.push_str(&note.content[last..reference.span.start]);
+            content.push_str(&replacement(reference));
+            last = reference.span.end;
+        }
+        content.push_str(&note.content[last..]);
+
+        let id = note.id;
+        let (links, names) = self.resolve_links(&note.owner, &content);
+        self.repo.update(Note {
+            content,
+            updated_at: Utc::now(),
+            ..note
+        })?;
+        self.repo.set_links(id, &links, &names)
+    }
+
+    /// Reads a partial note, making sure it is owned by the current user and in the trash.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::PermissionDenied` if the user does not own the note.
+    /// - `NoteValidationError::NoteNotTrashed` if the note isn't in the trash.
+    /// - Other repository errors if the note cannot be read.
+    fn get_trashed_note(&self, id: NoteId) -> Result<PartialNote> {
+        let partial_note = self.get_partial_note(id)?;
+        self.authorize(id, &partial_note.owner, Permission::Manage)?;
+        if partial_note.deleted_at.is_none() {
+            return Err(NoteValidationError::NoteNotTrashed(id).into());
+        }
+        Ok(partial_note)
+    }
+
+    /// Checks if the current user is the given owner, or a member of the group with that name
+    fn is_owner(&self, owner: &str) -> bool {
+        self.is_owner_as(&self.user, owner)
+    }
+
+    /// Checks if a given user is the given owner, or a member of the group with that name
+    fn is_owner_as(&self, user: &str, owner: &str) -> bool {
+        owner == user || self.groups.is_member(owner, user)
+    }
+
+    /// How long notes stay in the trash before they are purged
+    fn trash_retention(&self) -> TimeDelta {
+        TimeDelta::days(i64::from(self.trash_retention_days))
+    }
+
+    /// Extracts referenced note IDs in the form of `[[id]]`, `[[Name]]` or either with `|display text` from the
+    /// given string, using the same parser as `read_note` does for expanding them. Names are resolved to IDs.
+    ///
+    /// # Returns
+    ///
+    /// A vector of note IDs in order of appearance.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `resolve_target` for the first name that can't be resolved.
+    fn get_references(&self, s: &str) -> Result<Vec<NoteId>> {
+        references::parse(s)
+            .iter()
+            .map(|reference| self.resolve_target(&reference.target))
+            .collect()
+    }
+
+    /// Finds the notes that can be reached from a note by following references, including the note itself
+    fn reachable(links: &BTreeMap<NoteId, BTreeSet<NoteId>>, root: NoteId) -> BTreeSet<NoteId> {
+        let mut reached = BTreeSet::from([root]);
+        let mut queue = vec![root];
+        while let Some(id) = queue.pop() {
+            for &target in links.get(&id).into_iter().flatten() {
+                if reached.insert(target) {
+                    queue.push(target);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Makes sure the headings of references like `[[1#Heading]]` exist in the referenced notes.
+    /// References to the note being saved itself are checked against its new content.
+    ///
+    /// # Parameters
+    ///
+    /// - `id`: ID of the note being saved
+    /// - `content`: New content of the note being saved
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::HeadingNotFound` if a referenced note has no such heading.
+    /// - Other validation or repository errors if a referenced note can't be resolved or read.
+    fn validate_headings(&self, id: NoteId, content: &str) -> Result<()> {
+        for reference in references::parse(content) {
+            let Some(heading) = reference.heading else {
+                continue;
+            };
+
+            let rid = self.resolve_target(&reference.target)?;
+            let found = if rid == id {
+                references::section(content, &heading).is_some()
+            } else {
+                match self.repo.read(rid) {
+                    Ok(note) => references::section(&note.content, &heading).is_some(),
+                    // Purged notes have no headings to check, whether references to them may stay is up to the caller
+                    Err(NoteError::Backend(BackendError::NoteNotFound(_))) => continue,
+                    Err(e) => return Err(e),
                 }
+            };
+            if !found {
+                return Err(NoteValidationError::HeadingNotFound { id: rid, heading }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves the references in the content of a note for the reference index, on behalf of its owner.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the referenced notes, leaving out references that don't resolve, like names that no longer
+    /// match a single note, and every name used in a reference, so those can be resolved again later.
+    fn resolve_links(&self, owner: &str, content: &str) -> (Vec<NoteId>, Vec<String>) {
+        let references = references::parse(content);
+        let links = references
+            .iter()
+            .filter_map(|reference| self.resolve_target_as(owner, &reference.target).ok())
+            .collect();
+        let names = references
+            .into_iter()
+            .filter_map(|reference| match reference.target {
+                Target::Name(name) => Some(name),
+                Target::Id(_) => None,
             })
-            .collect()
+            .collect();
+        (links, names)
+    }
+
+    /// Resolves the name references to any of the given names again for the reference index, after a change
+    /// that can make them point at another note, like a note being renamed, trashed, restored or shared.
+    ///
+    /// Only the notes the reference index lists for those names are read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reference index can't be read or the references can't be stored.
+    fn reindex_name_references(&self, names: &[&str]) -> Result<()> {
+        for id in self.repo.name_backlinks(names)? {
+            // Corrupted notes have no references worth indexing
+            let Ok(note) = self.repo.read(id) else {
+                continue;
+            };
+            let (links, referenced_names) = self.resolve_links(&note.owner, &note.content);
+            self.repo.set_links(id, &links, &referenced_names)?;
+        }
+        Ok(())
+    }
+
+    /// Finds the ID of the note a reference points at. Names match the notes outside the trash that the
+    /// current user can read, so other users' notes with the same name don't make a name ambiguous.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `NoteValidationError::ReferenceNameNotFound` if no such note has the name.
+    /// - `NoteValidationError::ReferenceNameAmbiguous` if several of them have it.
+    /// - Other repository errors if the notes can't be looked up.
+    fn resolve_target(&self, target: &Target) -> Result<NoteId> {
+        self.resolve_target_as(&self.user, target)
+    }
+
+    /// Finds the ID of the note a reference points at like `resolve_target`, but among the notes a given user,
+    /// or group, can read.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `resolve_target`.
+    fn resolve_target_as(&self, reader: &str, target: &Target) -> Result<NoteId> {
+        let name = match target {
+            Target::Id(id) => return Ok(*id),
+            Target::Name(name) => name,
+        };
+
+        // Every grant allows reading, so these are exactly the notes the reader can read
+        let mut owners = self.groups.groups_of(reader);
+        owners.push(reader.to_string());
+        let ids: Vec<NoteId> = self
+            .repo
+            .find_by_name(name, &owners, reader)?
+            .into_iter()
+            .filter(|note| note.deleted_at.is_none())
+            .map(|note| note.id)
+            .collect();
+
+        match ids.as_slice() {
+            [] => Err(NoteValidationError::ReferenceNameNotFound(name.clone()).into()),
+            [id] => Ok(*id),
+            _ => Err(NoteValidationError::ReferenceNameAmbiguous {
+                name: name.clone(),
+                ids,
+            }
+            .into()),
+        }
     }
 
     /// Reads a note partially (e.g., ID and owner) without full content.
@@ -326,7 +1527,395 @@ impl NoteService {
     /// # Errors
     ///
     /// Returns an error if the note cannot be found or read from the repository.
-    fn get_partial_note(&self, id: u16) -> Result<PartialNote> {
+    fn get_partial_note(&self, id: NoteId) -> Result<PartialNote> {
         self.repo.read_partial(id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::{FilesystemBackend, InMemoryBackend, SqliteBackend};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn service(repo: Box<dyn NoteBackend>) -> NoteService {
+        NoteService::new(
+            repo,
+            "ctf".to_string(),
+            64,
+            4096,
+            1000,
+            30,
+            Groups::default(),
+        )
+    }
+
+    fn memory_service() -> NoteService {
+        service(Box::new(InMemoryBackend::new()))
+    }
+
+    fn create(service: &NoteService, name: &str, content: &str) -> NoteId {
+        service
+            .create_note(name.to_string(), content.to_string(), &[], None)
+            .unwrap()
+    }
+
+    #[test]
+    fn expansion_of_many_references_is_bounded() {
+        let service = memory_service().with_max_reference_depth(4);
+        let mut target = create(&service, "D", &"d".repeat(256));
+        for name in ["C", "B", "A"] {
+            target = create(&service, name, &format!("[[{target}]]").repeat(51));
+        }
+
+        let content = service.read_note(target).unwrap().content;
+
+        // Without a budget, this would be 51 * 51 * 51 quotes of D, tens of megabytes
+        assert!(content.len() <= MAX_EXPANDED_SIZE + 256);
+        assert!(content.starts_with(">>> #2 B"));
+        assert!(content.ends_with("[[2]]"));
+    }
+
+    #[test]
+    fn tombstones_resolve_names_as_the_referrer_owner() {
+        let mut service = memory_service();
+        service.groups.add("team".to_string(), ["ctf".to_string()]);
+        let target = service
+            .create_note(
+                "Foo".to_string(),
+                "target".to_string(),
+                &[],
+                Some("team".to_string()),
+            )
+            .unwrap();
+        let referrer = service
+            .create_note(
+                "Index".to_string(),
+                "see [[Foo]]".to_string(),
+                &[],
+                Some("team".to_string()),
+            )
+            .unwrap();
+        // Makes the name ambiguous for the deleting user, but not for the team
+        create(&service, "Foo", "private");
+
+        let changes = service
+            .delete_note_cascading(target, Cascade::Tombstone)
+            .unwrap();
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].note_id, referrer);
+        assert_eq!(
+            changes[1].action,
+            CascadeAction::Tombstoned { references: 1 }
+        );
+        assert_eq!(
+            service.repo.read(referrer).unwrap().content,
+            "see [deleted: Foo]"
+        );
+    }
+
+    #[test]
+    fn tombstones_replace_references_and_keep_a_revision() {
+        let service = memory_service();
+        let target = create(&service, "Target", "content");
+        let other = create(&service, "Other", "content");
+        let content = format!("([[{target}]]), [[{target}|alias]] and [[{other}]]");
+        let referrer = create(&service, "Referrer", &content);
+
+        let changes = service
+            .delete_note_cascading(target, Cascade::Tombstone)
+            .unwrap();
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].action, CascadeAction::Trashed);
+        assert_eq!(changes[1].note_id, referrer);
+        assert_eq!(
+            changes[1].action,
+            CascadeAction::Tombstoned { references: 2 }
+        );
+        assert_eq!(
+            service.repo.read(referrer).unwrap().content,
+            format!("([deleted: #{target}]), [deleted: alias] and [[{other}]]")
+        );
+        assert_eq!(
+            service.list_revisions(referrer).unwrap()[0].content,
+            content
+        );
+        assert!(service.backlinks(target).unwrap().is_empty());
+    }
+
+    #[test]
+    fn cascading_delete_trashes_only_direct_referrers() {
+        let service = memory_service();
+        let target = create(&service, "Target", "target");
+        let referrer = create(&service, "Referrer", &format!("see [[{target}]]"));
+        let indirect = create(&service, "Indirect", &format!("see [[{referrer}]]"));
+
+        let changes = service
+            .delete_note_cascading(target, Cascade::Delete)
+            .unwrap();
+
+        let trashed: Vec<NoteId> = changes.iter().map(|change| change.note_id).collect();
+        assert_eq!(trashed, [target, referrer]);
+        assert!(service.repo.read(referrer).unwrap().deleted_at.is_some());
+        assert!(service.repo.read(indirect).unwrap().deleted_at.is_none());
+    }
+
+    #[test]
+    fn renaming_resolves_name_references_again() {
+        let dir = TempDir::new().unwrap();
+        let backends: [Box<dyn NoteBackend>; 2] = [
+            Box::new(FilesystemBackend::new(dir.path().to_str().unwrap()).unwrap()),
+            Box::new(SqliteBackend::new(":memory:").unwrap()),
+        ];
+        for repo in backends {
+            let service = service(repo);
+            service.index_links().unwrap();
+            let renamed = create(&service, "Foo", "first");
+            let referrer = create(&service, "Referrer", "see [[Foo]]");
+            let backlinks = |id| -> Vec<NoteId> {
+                service
+                    .backlinks(id)
+                    .unwrap()
+                    .into_iter()
+                    .map(|note| note.id)
+                    .collect()
+            };
+            assert_eq!(backlinks(renamed), [referrer]);
+
+            let mut note = service.repo.read(renamed).unwrap();
+            note.name = "Bar".to_string();
+            service.update_note(note, &[]).unwrap();
+            assert!(backlinks(renamed).is_empty());
+
+            let replacement = create(&service, "Foo", "second");
+            assert_eq!(backlinks(replacement), [referrer]);
+        }
+    }
+
+    #[test]
+    fn references_added_through_a_write_grant_dont_block_deletion() {
+        let mut service = memory_service();
+        service.user = "alice".to_string();
+        let shared = create(&service, "Shared", "alice's");
+        service
+            .share_note(shared, "ctf", Permission::Write)
+            .unwrap();
+        service.user = "ctf".to_string();
+        let private = create(&service, "Private", "ctf's");
+        let mut note = service.repo.read(shared).unwrap();
+        note.content = format!("see [[{private}]]");
+        service.update_note(note, &[]).unwrap();
+
+        service.delete_note(private).unwrap();
+        service.purge_note(private).unwrap();
+
+        service.user = "alice".to_string();
+        assert_eq!(
+            service.read_note(shared).unwrap().content,
+            format!("see >>> #{private} [note not found]")
+        );
+        // The dangling reference doesn't keep the note from being edited
+        let mut note = service.repo.read(shared).unwrap();
+        note.content.push_str(", and more");
+        service.update_note(note, &[]).unwrap();
+    }
+
+    #[test]
+    fn reference_to_a_note_no_longer_shared_is_not_expanded() {
+        let mut service = memory_service();
+        service.user = "alice".to_string();
+        let secret = create(&service, "Secret", "classified");
+        service.share_note(secret, "ctf", Permission::Read).unwrap();
+        service.user = "ctf".to_string();
+        let referrer = create(&service, "Referrer", &format!("see [[{secret}]]"));
+        assert!(service
+            .read_note(referrer)
+            .unwrap()
+            .content
+            .contains("classified"));
+
+        service.user = "alice".to_string();
+        service.unshare_note(secret, "ctf").unwrap();
+        service.user = "ctf".to_string();
+        assert_eq!(
+            service.read_note(referrer).unwrap().content,
+            format!("see >>> #{secret} [access denied]")
+        );
+    }
+
+    #[test]
+    fn reference_to_a_reused_id_is_not_expanded() {
+        let mut service = memory_service();
+        let target = create(&service, "Target", "ctf's");
+        service
+            .share_note(target, "alice", Permission::Read)
+            .unwrap();
+        service.user = "alice".to_string();
+        let referrer = create(&service, "Referrer", &format!("see [[{target}]]"));
+        service.user = "ctf".to_string();
+        service.delete_note(target).unwrap();
+        service.purge_note(target).unwrap();
+
+        service.user = "bob".to_string();
+        assert_eq!(create(&service, "Secret", "bob's"), target);
+
+        service.user = "alice".to_string();
+        assert_eq!(
+            service.read_note(referrer).unwrap().content,
+            format!("see >>> #{target} [access denied]")
+        );
+    }
+
+    #[test]
+    fn references_next_to_punctuation_are_validated_and_expanded() {
+        let service = memory_service();
+        let target = create(&service, "Target", "content");
+        let referrer = create(
+            &service,
+            "Referrer",
+            &format!("([[{target}]]), [[{target}]]. [[{target}|alias]]"),
+        );
+
+        let quote = |title: &str| format!(">>> #{target} {title}\n>\n> content");
+        assert_eq!(
+            service.read_note(referrer).unwrap().content,
+            format!(
+                "({}), {}. {}",
+                quote("Target"),
+                quote("Target"),
+                quote("alias")
+            )
+        );
+        assert!(matches!(
+            service.create_note("Broken".to_string(), "([[99]]).".to_string(), &[], None),
+            Err(NoteError::Validation(
+                NoteValidationError::ReferenceNotFound(99)
+            ))
+        ));
+    }
+
+    #[test]
+    fn names_resolve_among_the_notes_the_user_can_read() {
+        let mut service = memory_service();
+        service.user = "alice".to_string();
+        create(&service, "Foo", "alice's");
+        service.user = "ctf".to_string();
+        let foo = create(&service, "Foo", "ctf's");
+
+        // Notes the user can't read don't make a name ambiguous
+        let referrer = create(&service, "Referrer", "see [[Foo]]");
+        assert_eq!(
+            service.read_note(referrer).unwrap().content,
+            format!("see >>> #{foo} Foo\n>\n> ctf's")
+        );
+        assert!(matches!(
+            service.create_note("Missing".to_string(), "[[Bar]]".to_string(), &[], None),
+            Err(NoteError::Validation(NoteValidationError::ReferenceNameNotFound(name))) if name == "Bar"
+        ));
+
+        let other = create(&service, "Foo", "another");
+        assert_eq!(
+            service.read_note(referrer).unwrap().content,
+            "see >>> 'Foo' [ambiguous name]"
+        );
+        assert!(matches!(
+            service.create_note("Ambiguous".to_string(), "[[Foo]]".to_string(), &[], None),
+            Err(NoteError::Validation(NoteValidationError::ReferenceNameAmbiguous { ids, .. }))
+                if ids == [foo, other]
+        ));
+    }
+
+    #[test]
+    fn heading_references_expand_only_their_section() {
+        let service = memory_service();
+        let target = create(
+            &service,
+            "Target",
+            "intro\n\n## Setup\n\ninstall it\n\n## Usage\n\nrun it",
+        );
+        let referrer = create(&service, "Referrer", &format!("see [[{target}#setup]]"));
+
+        assert_eq!(
+            service.read_note(referrer).unwrap().content,
+            format!("see >>> #{target} Target#setup\n>\n> install it")
+        );
+        assert!(matches!(
+            service.create_note("Broken".to_string(), format!("[[{target}#Missing]]"), &[], None),
+            Err(NoteError::Validation(NoteValidationError::HeadingNotFound { id, heading }))
+                if id == target && heading == "Missing"
+        ));
+    }
+
+    #[test]
+    fn corrupted_note_file_keeps_its_id() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("00000.note"), "corrupted").unwrap();
+        let service = service(Box::new(
+            FilesystemBackend::new(dir.path().to_str().unwrap()).unwrap(),
+        ));
+
+        assert_eq!(create(&service, "First", "content"), 1);
+        assert_eq!(create(&service, "Second", "content"), 2);
+    }
+
+    #[test]
+    fn reference_graph_leaves_out_unreadable_notes() {
+        let dir = TempDir::new().unwrap();
+        let service = service(Box::new(
+            FilesystemBackend::new(dir.path().to_str().unwrap()).unwrap(),
+        ));
+        let target = create(&service, "Target", "content");
+        let referrer = create(&service, "Referrer", "see [[Target]]");
+        // Parses, but has no content to read
+        fs::write(
+            dir.path().join("00002.note"),
+            "noters-note 2\nname 5\nEmpty\nowner 3\nctf\ncreated_at 0\nupdated_at 0\ncontent 0\n\n",
+        )
+        .unwrap();
+
+        let graph = service.reference_graph(None).unwrap();
+
+        let nodes: Vec<NoteId> = graph.nodes.iter().map(|node| node.id).collect();
+        assert_eq!(nodes, vec![target, referrer]);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(
+            (graph.edges[0].source, graph.edges[0].target),
+            (referrer, target)
+        );
+    }
+
+    #[test]
+    fn check_quarantines_unparseable_notes_and_hides_unreadable_details() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("00000.note"), "corrupted").unwrap();
+        let mut service = service(Box::new(
+            FilesystemBackend::new(dir.path().to_str().unwrap()).unwrap(),
+        ));
+        service.user = "alice".to_string();
+        let target = create(&service, "Secret plans", "content");
+        let referrer = create(&service, "Index", "see [[Secret plans|the plans]]");
+        let mut renamed = service.repo.read(target).unwrap();
+        renamed.name = "Renamed".to_string();
+        service.update_note(renamed, &[]).unwrap();
+
+        service.user = "ctf".to_string();
+        let issues = service.check(true).unwrap();
+
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].note_id, 0);
+        assert_eq!(issues[0].kind, IssueKind::Corrupted);
+        assert!(issues[0].fixed);
+        assert_eq!(service.repo.list_ids().unwrap(), vec![target, referrer]);
+        assert_eq!(issues[1].note_id, referrer);
+        assert_eq!(issues[1].kind, IssueKind::BrokenReference);
+        assert!(!issues[1].detail.contains("plans"));
+        assert!(!issues[1].fixed);
+        assert_eq!(
+            service.repo.read(referrer).unwrap().content,
+            "see [[Secret plans|the plans]]"
+        );
+    }
+}
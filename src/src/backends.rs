@@ -1,7 +1,292 @@
 pub mod filesystem;
+pub mod json;
+pub mod memory;
 pub mod sqlite;
 
 pub use filesystem::FilesystemBackend;
+pub use json::JsonBackend;
+pub use memory::InMemoryBackend;
 pub use sqlite::SqliteBackend;
 
-pub use crate::{BackendError, Note, NoteBackend, NoteError, PartialNote, Result};
+pub use crate::{
+    BackendError, Grant, ListQuery, Note, NoteBackend, NoteError, NoteId, PartialNote, Permission,
+    Result, Revision, SearchResult, SortDirection, SortKey, TrashFilter,
+};
+
+/// Splits a search query into lowercase words, which must each start a word in the name or content of a note,
+/// like the prefix queries `SqliteBackend` sends to its full-text index
+fn search_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Finds the first word in a text starting with a lowercase term, returning the byte range of the whole word
+fn find_word(text: &str, term: &str) -> Option<(usize, usize)> {
+    let mut offset = 0;
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let start = offset;
+        offset += word.len() + 1;
+        if !word.is_empty() && word.to_lowercase().starts_with(term) {
+            return Some((start, start + word.len()));
+        }
+    }
+    None
+}
+
+/// Matches the name and content of a note against search terms one line at a time,
+/// so backends can search notes without holding them in memory
+struct LineSearch<'a> {
+    terms: &'a [String],
+    found: Vec<bool>,
+    /// Snippet around the first match in the content
+    snippet: Option<String>,
+    /// First line of the content, the snippet when only the name matches
+    first_line: Option<String>,
+}
+
+impl<'a> LineSearch<'a> {
+    fn new(terms: &'a [String]) -> Self {
+        Self {
+            terms,
+            found: vec![false; terms.len()],
+            snippet: None,
+            first_line: None,
+        }
+    }
+
+    /// Matches a line of the name of the note
+    fn name_line(&mut self, line: &str) {
+        self.find(line);
+    }
+
+    /// Matches a line of the content of the note, taking the snippet from the first line with a match
+    fn content_line(&mut self, line: &str) {
+        if let Some((start, end)) = self.find(line) {
+            self.snippet
+                .get_or_insert_with(|| format_snippet(line, start, end));
+        }
+        self.first_line.get_or_insert_with(|| line.to_string());
+    }
+
+    /// Marks the terms found in a line, returning the range of the word matched by the first of them
+    fn find(&mut self, line: &str) -> Option<(usize, usize)> {
+        let mut first = None;
+        for (term, found) in self.terms.iter().zip(self.found.iter_mut()) {
+            if let Some(range) = find_word(line, term) {
+                *found = true;
+                first.get_or_insert(range);
+            }
+        }
+        first
+    }
+
+    /// Whether the remaining lines can't change the result
+    fn is_done(&self) -> bool {
+        self.snippet.is_some() && self.found.iter().all(|found| *found)
+    }
+
+    /// Returns the snippet if every term was found, taken from the first content line if no content line matched
+    fn finish(self) -> Option<String> {
+        self.found
+            .iter()
+            .all(|found| *found)
+            .then(|| self.snippet.or(self.first_line).unwrap_or_default())
+    }
+}
+
+/// Cuts a line down to the text around a match, and wraps the match in `[` and `]`
+fn format_snippet(line: &str, start: usize, end: usize) -> String {
+    const CONTEXT: usize = 32;
+
+    let before = &line[..start];
+    let before = before
+        .char_indices()
+        .rev()
+        .nth(CONTEXT - 1)
+        .map_or(before, |(i, _)| &before[i..]);
+    let after = &line[end..];
+    let after = after
+        .char_indices()
+        .nth(CONTEXT)
+        .map_or(after, |(i, _)| &after[..i]);
+
+    let prefix = if before.len() < start { "..." } else { "" };
+    let suffix = if after.len() < line.len() - end {
+        "..."
+    } else {
+        ""
+    };
+    format!("{prefix}{before}[{}]{after}{suffix}", &line[start..end])
+}
+
+/// Cases every backend must handle the same way, run against each backend that keeps all of its state in one place
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{SubsecRound, Utc};
+    use tempfile::TempDir;
+
+    fn backends(dir: &TempDir) -> Vec<(&'static str, Box<dyn NoteBackend>)> {
+        let json_path = dir.path().join("notes.json");
+        vec![
+            ("sqlite", Box::new(SqliteBackend::new(":memory:").unwrap())),
+            ("memory", Box::new(InMemoryBackend::new())),
+            (
+                "json",
+                Box::new(JsonBackend::new(json_path.to_str().unwrap()).unwrap()),
+            ),
+        ]
+    }
+
+    fn note(id: NoteId, content: &str) -> Note {
+        let now = Utc::now().trunc_subsecs(0);
+        Note {
+            id,
+            owner: "ctf".to_string(),
+            name: format!("Note {id}"),
+            content: content.to_string(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        }
+    }
+
+    fn listed(backend: &dyn NoteBackend, trash: TrashFilter) -> Vec<NoteId> {
+        let query = ListQuery {
+            trash,
+            ..ListQuery::default()
+        };
+        backend
+            .list(&query)
+            .unwrap()
+            .into_iter()
+            .map(|note| note.id)
+            .collect()
+    }
+
+    #[test]
+    fn create_fails_for_existing_note() {
+        let dir = TempDir::new().unwrap();
+        for (name, backend) in backends(&dir) {
+            backend.create(note(1, "first")).unwrap();
+
+            assert!(
+                matches!(
+                    backend.create(note(1, "second")),
+                    Err(NoteError::Backend(BackendError::Duplicate))
+                ),
+                "{name}"
+            );
+            assert_eq!(backend.read(1).unwrap().content, "first", "{name}");
+        }
+    }
+
+    #[test]
+    fn update_and_delete_fail_for_missing_note() {
+        let dir = TempDir::new().unwrap();
+        for (name, backend) in backends(&dir) {
+            assert!(
+                matches!(
+                    backend.update(note(1, "content")),
+                    Err(NoteError::Backend(BackendError::NoteNotFound(1)))
+                ),
+                "{name}"
+            );
+            assert!(
+                matches!(
+                    backend.delete(1),
+                    Err(NoteError::Backend(BackendError::NoteNotFound(1)))
+                ),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn find_by_name_matches_owners_and_grantee() {
+        let dir = TempDir::new().unwrap();
+        for (name, backend) in backends(&dir) {
+            for (id, owner) in [(1, "ctf"), (2, "team"), (3, "alice"), (4, "bob")] {
+                backend
+                    .create(Note {
+                        owner: owner.to_string(),
+                        name: "Foo".to_string(),
+                        ..note(id, "content")
+                    })
+                    .unwrap();
+            }
+            backend.create(note(5, "other name")).unwrap();
+            backend.set_grant(3, "ctf", Permission::Read).unwrap();
+
+            let owners = ["ctf".to_string(), "team".to_string()];
+            let found: Vec<NoteId> = backend
+                .find_by_name("Foo", &owners, "ctf")
+                .unwrap()
+                .into_iter()
+                .map(|note| note.id)
+                .collect();
+            assert_eq!(found, vec![1, 2, 3], "{name}");
+        }
+    }
+
+    #[test]
+    fn trashed_note_is_listed_until_restored() {
+        let dir = TempDir::new().unwrap();
+        for (name, backend) in backends(&dir) {
+            backend.create(note(1, "kept")).unwrap();
+            backend.create(note(2, "trashed")).unwrap();
+            let deleted_at = Utc::now().trunc_subsecs(0);
+
+            backend.set_deleted(2, Some(deleted_at)).unwrap();
+            assert_eq!(
+                backend.read(2).unwrap().deleted_at,
+                Some(deleted_at),
+                "{name}"
+            );
+            assert_eq!(listed(&*backend, TrashFilter::Exclude), vec![1], "{name}");
+            assert_eq!(listed(&*backend, TrashFilter::Only), vec![2], "{name}");
+            assert_eq!(
+                listed(&*backend, TrashFilter::Include),
+                vec![1, 2],
+                "{name}"
+            );
+
+            backend.set_deleted(2, None).unwrap();
+            assert_eq!(backend.read(2).unwrap().deleted_at, None, "{name}");
+            assert_eq!(
+                listed(&*backend, TrashFilter::Exclude),
+                vec![1, 2],
+                "{name}"
+            );
+            assert!(listed(&*backend, TrashFilter::Only).is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn rollback_undoes_failed_transaction() {
+        let dir = TempDir::new().unwrap();
+        for (name, backend) in backends(&dir) {
+            backend.create(note(1, "before")).unwrap();
+
+            backend.begin().unwrap();
+            backend.create(note(2, "created")).unwrap();
+            backend.update(note(1, "changed")).unwrap();
+            assert!(backend.update(note(3, "missing")).is_err(), "{name}");
+            backend.rollback().unwrap();
+
+            assert_eq!(backend.read(1).unwrap().content, "before", "{name}");
+            assert!(backend.list_revisions(1).unwrap().is_empty(), "{name}");
+            assert!(
+                matches!(
+                    backend.read(2),
+                    Err(NoteError::Backend(BackendError::NoteNotFound(2)))
+                ),
+                "{name}"
+            );
+            assert_eq!(listed(&*backend, TrashFilter::Include), vec![1], "{name}");
+        }
+    }
+}
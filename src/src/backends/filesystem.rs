@@ -1,41 +1,381 @@
-use super::{BackendError, Note, NoteBackend, NoteError, PartialNote, Result};
+use super::{
+    search_terms, BackendError, Grant, LineSearch, ListQuery, Note, NoteBackend, NoteError, NoteId,
+    PartialNote, Permission, Result, Revision, SearchResult,
+};
+use chrono::{DateTime, SubsecRound, Utc};
 use log::trace;
 use std::{
-    fs::{self, File},
-    io::{Read, Write},
-    path::PathBuf,
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
 };
 
+/// Creation, modification and, for notes in the trash, deletion time of a note
+type Timestamps = (DateTime<Utc>, DateTime<Utc>, Option<DateTime<Utc>>);
+
 #[derive(Debug)]
 pub struct FilesystemBackend {
     base_path: PathBuf,
+    /// Lock file of the notes directory, held while a transaction is active
+    lock: RefCell<Option<File>>,
+    /// Whether the notes directory is locked for a single change made outside of a transaction
+    locked_for_change: Cell<bool>,
 }
 
 impl FilesystemBackend {
-    /// Creates a new `FilesystemBackend` instance with the given base directory
+    /// Creates a new `FilesystemBackend` instance with the given base directory,
+    /// and rolls back any transaction left behind by a crashed process
     ///
     /// # Errors
     ///
-    /// Returns `BackendError::DirectoryCreationError` if the base directory cannot be created
+    /// Returns:
+    /// - `BackendError::DirectoryCreationError` if the base directory cannot be created
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    /// - `BackendError::JournalError` if a leftover transaction cannot be rolled back
     pub fn new(path: &str) -> Result<Self> {
         let base_path = PathBuf::from(path);
         fs::create_dir_all(&base_path)
             .map_err(|e| NoteError::Backend(BackendError::DirectoryCreationError(e)))?;
         trace!("Created directory for notes: {}", &base_path.display());
-        Ok(Self { base_path })
+
+        let backend = Self {
+            base_path,
+            lock: RefCell::new(None),
+            locked_for_change: Cell::new(false),
+        };
+        let _lock = backend.lock_directory()?;
+        backend.recover_journal()?;
+        Ok(backend)
+    }
+
+    /// Path of the file locked by transactions
+    fn lock_path(&self) -> PathBuf {
+        self.base_path.join(".lock")
+    }
+
+    /// Path of the directory holding the original versions of files modified in the active transaction
+    fn journal_path(&self) -> PathBuf {
+        self.base_path.join(".journal")
+    }
+
+    /// Path the journal is moved to when its transaction commits, right before it is deleted
+    fn committed_journal_path(&self) -> PathBuf {
+        self.base_path.join(".journal.committed")
+    }
+
+    /// Flushes the entries of the notes directory to disk, so created, renamed and removed files survive a crash
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be opened or synced
+    fn sync_directory(&self) -> io::Result<()> {
+        File::open(&self.base_path)?.sync_all()
     }
 
-    /// Constructs a filesystem path for the note file based on its ID
-    fn note_path(&self, id: u16) -> PathBuf {
+    /// Opens the lock file and takes an exclusive advisory lock on it, waiting for other processes to release it.
+    /// The lock is released when the returned file is dropped
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::LockError` if the lock file cannot be opened or locked
+    fn lock_directory(&self) -> Result<File> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(self.lock_path())
+            .map_err(|e| NoteError::Backend(BackendError::LockError(e)))?;
+        file.lock()
+            .map_err(|e| NoteError::Backend(BackendError::LockError(e)))?;
+        trace!("Locked notes directory");
+        Ok(file)
+    }
+
+    /// Runs a change with the notes directory locked, so changes from other processes can't interleave with it.
+    /// Changes made in a transaction, or by another change, already hold the lock
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::LockError` if the notes directory cannot be locked, or the error of the change
+    fn with_lock<T>(&self, change: impl FnOnce() -> Result<T>) -> Result<T> {
+        if self.lock.borrow().is_some() || self.locked_for_change.get() {
+            return change();
+        }
+
+        let _lock = self.lock_directory()?;
+        self.locked_for_change.set(true);
+        let result = change();
+        self.locked_for_change.set(false);
+        result
+    }
+
+    /// Saves the current state of a file to the journal before it is first modified in a transaction,
+    /// so it can be restored on rollback. Does nothing outside of transactions
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::JournalError` if the file cannot be saved to the journal
+    fn journal(&self, path: &Path) -> Result<()> {
+        if self.lock.borrow().is_none() {
+            return Ok(());
+        }
+        let Some(name) = path.file_name() else {
+            return Ok(());
+        };
+
+        let journal_path = self.journal_path();
+        let backup = journal_path.join(name);
+        let mut absent = name.to_os_string();
+        absent.push(".absent");
+        let absent = journal_path.join(absent);
+        if backup.exists() || absent.exists() {
+            return Ok(());
+        }
+
+        let result = if path.exists() {
+            // Copy under a temporary name first, so a crash never leaves a partial backup to restore
+            let mut partial = name.to_os_string();
+            partial.push(".partial");
+            let partial = journal_path.join(partial);
+            fs::copy(path, &partial).and_then(|_| fs::rename(&partial, &backup))
+        } else {
+            File::create(&absent).map(|_| ())
+        };
+        result.map_err(|e| NoteError::Backend(BackendError::JournalError(e)))?;
+        trace!("Journaled file: {}", path.display());
+        Ok(())
+    }
+
+    /// Restores every file saved in the journal, removes files that didn't exist before
+    /// the transaction, and deletes the journal. Leftovers of a committed journal are just deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::JournalError` if the journal cannot be read or a file cannot be restored
+    fn recover_journal(&self) -> Result<()> {
+        let result = (|| {
+            let committed_path = self.committed_journal_path();
+            if committed_path.exists() {
+                fs::remove_dir_all(&committed_path)?;
+            }
+
+            let journal_path = self.journal_path();
+            if !journal_path.exists() {
+                return Ok(());
+            }
+            for entry in fs::read_dir(&journal_path)? {
+                let path = entry?.path();
+                let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+
+                if let Some(original) = name.strip_suffix(".absent") {
+                    match fs::remove_file(self.base_path.join(original)) {
+                        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                        _ => {}
+                    }
+                } else if !name.ends_with(".partial") {
+                    fs::rename(&path, self.base_path.join(name))?;
+                }
+                trace!("Restored file from journal: {name}");
+            }
+            fs::remove_dir_all(&journal_path)
+        })();
+        result.map_err(|e| NoteError::Backend(BackendError::JournalError(e)))
+    }
+
+    /// Constructs a filesystem path for the note file based on its ID.
+    /// IDs are padded to 5 digits like when they were 16-bit, so existing note directories keep working.
+    /// Larger IDs just get more digits
+    fn note_path(&self, id: NoteId) -> PathBuf {
         self.base_path.join(format!("{id:05}.note"))
     }
 
-    /// Lists all note files in the base directory
+    /// Constructs a filesystem path for the file holding a note's tags, one per line
+    fn tags_path(&self, id: NoteId) -> PathBuf {
+        self.base_path.join(format!("{id:05}.tags"))
+    }
+
+    /// Path of the directory holding copies of notes that couldn't be parsed, moved away by `quarantine`
+    fn quarantine_path(&self) -> PathBuf {
+        self.base_path.join("quarantine")
+    }
+
+    /// Path of the reference index, with one line per note listing the ID of the note followed by the IDs it references
+    fn links_path(&self) -> PathBuf {
+        self.base_path.join(".links")
+    }
+
+    /// Reads the reference index, or `None` if it doesn't exist yet
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::FileReadError` if the index exists but cannot be read
+    ///
+    /// # Note
+    ///
+    /// Silently skips malformed IDs
+    fn read_links(&self) -> Result<Option<BTreeMap<NoteId, Vec<NoteId>>>> {
+        let contents = match fs::read_to_string(self.links_path()) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(NoteError::Backend(BackendError::FileReadError(e))),
+        };
+
+        let mut links = BTreeMap::new();
+        for line in contents.lines() {
+            let mut ids = line.split(' ').filter_map(|id| id.parse::<NoteId>().ok());
+            if let Some(source) = ids.next() {
+                links.insert(source, ids.collect());
+            }
+        }
+        Ok(Some(links))
+    }
+
+    /// Writes the whole reference index
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::FileCreationError` if the index file cannot be created
+    /// - `BackendError::FileWriteError` if writing to the index file fails
+    fn write_links(&self, links: &BTreeMap<NoteId, Vec<NoteId>>) -> Result<()> {
+        let data = links
+            .iter()
+            .filter(|(_, targets)| !targets.is_empty())
+            .map(|(source, targets)| {
+                std::iter::once(source)
+                    .chain(targets)
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let path = self.links_path();
+        self.journal(&path)?;
+        write_atomically(&path, data.as_bytes())
+    }
+
+    /// Path of the index of references by name, with one `id name` pair per line for each name a note references
+    fn name_links_path(&self) -> PathBuf {
+        self.base_path.join(".name_links")
+    }
+
+    /// Reads the index of references by name, or `None` if it doesn't exist yet
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::FileReadError` if the index exists but cannot be read
+    ///
+    /// # Note
+    ///
+    /// Silently skips lines with a malformed ID
+    fn read_name_links(&self) -> Result<Option<BTreeMap<NoteId, Vec<String>>>> {
+        let contents = match fs::read_to_string(self.name_links_path()) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(NoteError::Backend(BackendError::FileReadError(e))),
+        };
+
+        let mut name_links: BTreeMap<NoteId, Vec<String>> = BTreeMap::new();
+        for line in contents.lines() {
+            if let Some((source, name)) = line.split_once(' ')
+                && let Ok(source) = source.parse::<NoteId>()
+            {
+                name_links.entry(source).or_default().push(name.to_string());
+            }
+        }
+        Ok(Some(name_links))
+    }
+
+    /// Writes the whole index of references by name
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::FileCreationError` if the index file cannot be created
+    /// - `BackendError::FileWriteError` if writing to the index file fails
+    fn write_name_links(&self, name_links: &BTreeMap<NoteId, Vec<String>>) -> Result<()> {
+        let data = name_links
+            .iter()
+            .flat_map(|(source, names)| names.iter().map(move |name| format!("{source} {name}")))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let path = self.name_links_path();
+        self.journal(&path)?;
+        write_atomically(&path, data.as_bytes())
+    }
+
+    /// Constructs a filesystem path for the file holding a note's grants, one `permission user` pair per line
+    fn grants_path(&self, id: NoteId) -> PathBuf {
+        self.base_path.join(format!("{id:05}.grants"))
+    }
+
+    /// Writes the grants of a note to its grants file, or removes the file if there are no grants
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::FileCreationError` if the grants file cannot be created
+    /// - `BackendError::FileWriteError` if writing to or removing the grants file fails
+    fn write_grants(&self, id: NoteId, grants: &[Grant]) -> Result<()> {
+        let path = self.grants_path(id);
+        self.journal(&path)?;
+        if grants.is_empty() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    Err(NoteError::Backend(BackendError::FileWriteError(e)))
+                }
+                _ => Ok(()),
+            };
+        }
+
+        let data = grants
+            .iter()
+            .map(|grant| format!("{} {}", grant.permission, grant.user))
+            .collect::<Vec<String>>()
+            .join("\n");
+        write_atomically(&path, data.as_bytes())?;
+        trace!("Wrote grants of note #{id} to file:\n{data}");
+        Ok(())
+    }
+
+    /// Constructs a filesystem path for a previous version of a note, stored in the same format as the note
+    fn revision_path(&self, id: NoteId, revision: u32) -> PathBuf {
+        self.base_path.join(format!("{id:05}.{revision}.rev"))
+    }
+
+    /// Finds the numbers of all saved revisions of a note, in ascending order
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::DirectoryReadError` if the directory cannot be read
+    fn revision_numbers(&self, id: NoteId) -> Result<Vec<u32>> {
+        let mut revisions: Vec<u32> = self
+            .list_files("rev")?
+            .iter()
+            .filter_map(|path| {
+                let (note_id, revision) = path.file_stem()?.to_str()?.split_once('.')?;
+                (note_id.parse::<NoteId>().ok()? == id).then(|| revision.parse().ok())?
+            })
+            .collect();
+        revisions.sort_unstable();
+        Ok(revisions)
+    }
+
+    /// Lists all files with the given extension in the base directory
     ///
     /// # Errors
     ///
     /// Returns `BackendError::DirectoryReadError` if the directory cannot be read or a file entry cannot be processed
-    fn list_note_files(&self) -> Result<Vec<PathBuf>> {
+    fn list_files(&self, extension: &str) -> Result<Vec<PathBuf>> {
         let entries = fs::read_dir(&self.base_path)
             .map_err(BackendError::DirectoryReadError)
             .map_err(NoteError::Backend)?;
@@ -52,38 +392,452 @@ impl FilesystemBackend {
                 .map_err(BackendError::DirectoryReadError)
                 .map_err(NoteError::Backend)?;
 
-            if file_type.is_file() {
-                files.push(entry.path());
+            let path = entry.path();
+            if file_type.is_file() && path.extension().is_some_and(|ext| ext == extension) {
+                files.push(path);
             }
         }
-        trace!("Found notes: {:?}", &files);
+        trace!("Found {extension} files: {:?}", &files);
         Ok(files)
     }
+
+    /// Reads and parses a note file by ID without validating its content
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::FileReadError` if the file cannot be read
+    /// - `BackendError::NoteCorrupted` if the file does not contain at least two lines (name and owner)
+    fn read_note_file(&self, id: NoteId) -> Result<Note> {
+        let file = File::open(self.note_path(id))
+            .map_err(|_| NoteError::Backend(BackendError::NoteNotFound(id)))?;
+        trace!("Opened file for note #{} for reading", &id);
+        parse_note_file(file, id)
+    }
+
+    /// Reads the name and content of a note file line by line, matching them against the given lowercase terms,
+    /// and stops reading as soon as the rest of the file can't change the result
+    ///
+    /// # Returns
+    ///
+    /// A snippet around the first match in the content if every term starts a word in the name or content,
+    /// otherwise `None`
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::FileReadError` if the file cannot be read
+    /// - `BackendError::NoteCorrupted` if the file cannot be parsed
+    fn search_note_file(&self, id: NoteId, terms: &[String]) -> Result<Option<String>> {
+        let file = File::open(self.note_path(id))
+            .map_err(|_| NoteError::Backend(BackendError::NoteNotFound(id)))?;
+        let mut reader = BufReader::new(file);
+        let mut search = LineSearch::new(terms);
+
+        let first =
+            read_line(&mut reader)?.ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
+        if first == NOTE_HEADER {
+            while !search.is_done() {
+                let Some(line) = read_line(&mut reader)? else {
+                    break;
+                };
+                let (key, value) = line
+                    .split_once(' ')
+                    .ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
+                if !TEXT_FIELDS.contains(&key) {
+                    continue;
+                }
+                let len = value
+                    .parse::<usize>()
+                    .map_err(|_| NoteError::Backend(BackendError::NoteCorrupted))?;
+                search_text_field(&mut reader, len, |line| match key {
+                    "name" => search.name_line(line),
+                    "content" => search.content_line(line),
+                    _ => {}
+                })?;
+            }
+        } else {
+            // Old format: a line each for the name and owner, maybe timestamps, then the content
+            search.name_line(&first);
+            read_line(&mut reader)?.ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
+            let mut line = read_line(&mut reader)?;
+            if line.as_deref().and_then(parse_timestamps).is_some() {
+                line = read_line(&mut reader)?;
+            }
+            while let Some(content_line) = line
+                && !search.is_done()
+            {
+                search.content_line(&content_line);
+                line = read_line(&mut reader)?;
+            }
+        }
+        Ok(search.finish())
+    }
+}
+
+/// Temporary files created by this process so far, to give each one a unique name
+static TEMPORARY_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Replaces the contents of a file without ever leaving it partially written. The data is written to a temporary
+/// file in the same directory and flushed to disk, then renamed over the file, and the directory is synced so the
+/// rename survives a crash too. The temporary file is removed if any step fails
+///
+/// # Errors
+///
+/// Returns:
+/// - `BackendError::FileCreationError` if the temporary file cannot be created
+/// - `BackendError::FileWriteError` if writing, syncing or renaming the temporary file fails
+pub(super) fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    write_through_temporary_file(path, data, |temporary_path| {
+        fs::rename(temporary_path, path)
+    })
+}
+
+/// Creates a file like `write_atomically`, but fails if it already exists, like opening it with `O_EXCL` would.
+/// The temporary file is hard linked to the new path instead of renamed, since linking never replaces a file
+///
+/// # Errors
+///
+/// Returns:
+/// - `BackendError::Duplicate` if the file already exists
+/// - `BackendError::FileCreationError` if the temporary file cannot be created
+/// - `BackendError::FileWriteError` if writing, syncing or linking the temporary file fails
+fn create_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    write_through_temporary_file(path, data, |temporary_path| {
+        fs::hard_link(temporary_path, path).and_then(|()| fs::remove_file(temporary_path))
+    })
+}
+
+/// Writes data to a temporary file next to the given path and syncs it, moves it into place with `move_file`,
+/// and syncs the directory. The temporary file is named after the process and a counter, so concurrent writers
+/// can't mix their data, and is removed if any step fails
+///
+/// # Errors
+///
+/// Returns:
+/// - `BackendError::Duplicate` if `move_file` fails because the file already exists
+/// - `BackendError::FileCreationError` if the temporary file cannot be created
+/// - `BackendError::FileWriteError` if any other step fails
+fn write_through_temporary_file(
+    path: &Path,
+    data: &[u8],
+    move_file: impl FnOnce(&Path) -> io::Result<()>,
+) -> Result<()> {
+    let mut temporary_name = OsString::from(".");
+    temporary_name.push(path.file_name().unwrap_or_default());
+    temporary_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temporary_path = path.with_file_name(temporary_name);
+
+    let mut file = inject_failure(WriteStep::Create, path)
+        .and_then(|()| File::create_new(&temporary_path))
+        .map_err(|e| NoteError::Backend(BackendError::FileCreationError(e)))?;
+    let result = inject_failure(WriteStep::Write, path)
+        .and_then(|()| file.write_all(data))
+        .and_then(|()| inject_failure(WriteStep::Sync, path))
+        .and_then(|()| file.sync_all())
+        .and_then(|()| inject_failure(WriteStep::Move, path))
+        .and_then(|()| move_file(&temporary_path))
+        .and_then(|()| inject_failure(WriteStep::SyncDirectory, path))
+        .and_then(|()| sync_parent_directory(path));
+
+    if let Err(e) = result {
+        // Only fails if the temporary file was already moved
+        let _ = fs::remove_file(&temporary_path);
+        return Err(NoteError::Backend(
+            if e.kind() == ErrorKind::AlreadyExists {
+                BackendError::Duplicate
+            } else {
+                BackendError::FileWriteError(e)
+            },
+        ));
+    }
+    Ok(())
+}
+
+/// Flushes the entries of the directory holding a file to disk, so the file being created, renamed or removed
+/// survives a crash
+///
+/// # Errors
+///
+/// Returns an error if the directory cannot be opened or synced
+fn sync_parent_directory(path: &Path) -> io::Result<()> {
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    File::open(directory)?.sync_all()
+}
+
+/// Steps of `write_atomically`, which tests can make fail to simulate a crash or a full disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WriteStep {
+    Create,
+    Write,
+    Sync,
+    Move,
+    SyncDirectory,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Step that `inject_failure` fails at on this thread, and the extension of the files it fails for
+    static FAILING_STEP: std::cell::Cell<Option<(WriteStep, &'static str)>> = const { std::cell::Cell::new(None) };
+}
+
+/// Fails right before the given step of writing a file if a test asked for it, and does nothing otherwise
+fn inject_failure(step: WriteStep, path: &Path) -> io::Result<()> {
+    if failing_step().is_some_and(|(failing, extension)| {
+        failing == step && path.extension().is_some_and(|ext| ext == extension)
+    }) {
+        Err(io::Error::other(format!(
+            "injected failure before {step:?}"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn failing_step() -> Option<(WriteStep, &'static str)> {
+    FAILING_STEP.get()
+}
+
+#[cfg(not(test))]
+const fn failing_step() -> Option<(WriteStep, &'static str)> {
+    None
+}
+
+/// First line of note files in the current format, version 2. Files without it are in the old format, version 1
+const NOTE_HEADER: &str = "noters-note 2";
+
+/// Fields holding arbitrary text, written as their length in bytes followed by the text itself on the next line
+const TEXT_FIELDS: [&str; 3] = ["name", "owner", "content"];
+
+/// Parses a note file without validating its content
+///
+/// Files in the current format start with a `noters-note 2` header line, followed by one field per line.
+/// Any other first line is the name of a note in the old format of plain lines, even one like `noters-note 1`,
+/// and the file is read as such until it is next written.
+///
+/// # Errors
+///
+/// Returns:
+/// - `BackendError::FileReadError` if the file cannot be read
+/// - `BackendError::NoteCorrupted` if a field is missing or malformed
+fn parse_note_file(mut file: File, id: NoteId) -> Result<Note> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| NoteError::Backend(BackendError::FileReadError(e)))?;
+
+    match contents.split_once('\n') {
+        Some((NOTE_HEADER, fields)) => {
+            parse_note_fields(fields, id).ok_or(NoteError::Backend(BackendError::NoteCorrupted))
+        }
+        _ => parse_legacy_note(&contents, &file, id),
+    }
+}
+
+/// Parses the fields of a note file in the current format, which can be in any order.
+/// Returns `None` if a field is missing, repeated, unknown or malformed
+fn parse_note_fields(mut rest: &str, id: NoteId) -> Option<Note> {
+    let mut fields = BTreeMap::new();
+    while !rest.is_empty() {
+        let (line, after) = rest.split_once('\n')?;
+        let (key, value) = line.split_once(' ')?;
+        let value = if TEXT_FIELDS.contains(&key) {
+            let len = value.parse::<usize>().ok()?;
+            rest = after.get(len..)?.strip_prefix('\n')?;
+            after.get(..len)?
+        } else {
+            rest = after;
+            value
+        };
+        if fields.insert(key, value).is_some() {
+            return None;
+        }
+    }
+
+    if !fields.keys().all(|key| {
+        TEXT_FIELDS.contains(key) || ["created_at", "updated_at", "deleted_at"].contains(key)
+    }) {
+        return None;
+    }
+
+    let timestamp = |key| DateTime::from_timestamp(fields.get(key)?.parse().ok()?, 0);
+    Some(Note {
+        id,
+        name: (*fields.get("name")?).to_string(),
+        owner: (*fields.get("owner")?).to_string(),
+        content: (*fields.get("content")?).to_string(),
+        created_at: timestamp("created_at")?,
+        updated_at: timestamp("updated_at")?,
+        deleted_at: match fields.get("deleted_at") {
+            Some(_) => Some(timestamp("deleted_at")?),
+            None => None,
+        },
+    })
+}
+
+/// Parses a note file in the old format, with lines of name, owner, timestamps and content
+///
+/// Files written before timestamps existed have no timestamp line, and get the
+/// file's modification time as both creation and modification time.
+/// The deletion time is only written for notes in the trash.
+///
+/// # Errors
+///
+/// Returns:
+/// - `BackendError::FileReadError` if the modification time of the file cannot be read
+/// - `BackendError::NoteCorrupted` if the file does not contain at least two lines (name and owner)
+fn parse_legacy_note(contents: &str, file: &File, id: NoteId) -> Result<Note> {
+    let mut lines = contents.lines().peekable();
+    let name = lines
+        .next()
+        .ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
+    let owner = lines
+        .next()
+        .ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
+
+    let (created_at, updated_at, deleted_at) =
+        if let Some(timestamps) = lines.peek().and_then(|l| parse_timestamps(l)) {
+            lines.next();
+            timestamps
+        } else {
+            let modified = file
+                .metadata()
+                .and_then(|m| m.modified())
+                .map_err(|e| NoteError::Backend(BackendError::FileReadError(e)))?;
+            let modified = DateTime::<Utc>::from(modified).trunc_subsecs(0);
+            (modified, modified, None)
+        };
+    let content = lines.collect::<Vec<&str>>().join("\n");
+
+    Ok(Note {
+        id,
+        name: name.to_string(),
+        owner: owner.to_string(),
+        content,
+        created_at,
+        updated_at,
+        deleted_at,
+    })
+}
+
+/// Serializes a note to the current on-disk format: a header, then one field per line.
+/// Text fields are prefixed with their length, so any name, owner or content round-trips exactly
+fn format_note(note: &Note) -> String {
+    let deleted_at = note.deleted_at.map_or_else(String::new, |deleted_at| {
+        format!("deleted_at {}\n", deleted_at.timestamp())
+    });
+    format!(
+        "{NOTE_HEADER}\n{}{}created_at {}\nupdated_at {}\n{deleted_at}{}",
+        format_text_field("name", &note.name),
+        format_text_field("owner", &note.owner),
+        note.created_at.timestamp(),
+        note.updated_at.timestamp(),
+        format_text_field("content", &note.content)
+    )
+}
+
+/// Formats a text field as its key and length in bytes, followed by the text itself on the next line
+fn format_text_field(key: &str, value: &str) -> String {
+    format!("{key} {}\n{value}\n", value.len())
+}
+
+/// Reads the next line of a file without its line ending, like `str::lines` splits it
+///
+/// # Errors
+///
+/// Returns `BackendError::FileReadError` if the file cannot be read
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = String::new();
+    let read = reader
+        .read_line(&mut line)
+        .map_err(|e| NoteError::Backend(BackendError::FileReadError(e)))?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+/// Reads a text field of the given length in bytes and the newline after it line by line,
+/// passing each line to `search`
+///
+/// # Errors
+///
+/// Returns:
+/// - `BackendError::FileReadError` if the file cannot be read
+/// - `BackendError::NoteCorrupted` if the field is cut short or isn't followed by a newline
+fn search_text_field(
+    reader: &mut impl BufRead,
+    len: usize,
+    mut search: impl FnMut(&str),
+) -> Result<()> {
+    let mut remaining = len + 1;
+    let mut line = String::new();
+    while remaining > 0 {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| NoteError::Backend(BackendError::FileReadError(e)))?;
+        if read == 0 || read > remaining {
+            return Err(NoteError::Backend(BackendError::NoteCorrupted));
+        }
+        remaining -= read;
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        search(line.strip_suffix('\r').unwrap_or(line));
+    }
+    Ok(())
+}
+
+/// Parses a timestamp line of the old format, e.g. `1755446400 1755450000`,
+/// or `1755446400 1755450000 1755453600` for a note in the trash
+fn parse_timestamps(line: &str) -> Option<Timestamps> {
+    let mut fields = line.split(' ');
+    let created_at = DateTime::from_timestamp(fields.next()?.parse().ok()?, 0)?;
+    let updated_at = DateTime::from_timestamp(fields.next()?.parse().ok()?, 0)?;
+    let deleted_at = match fields.next() {
+        Some(deleted_at) => Some(DateTime::from_timestamp(deleted_at.parse().ok()?, 0)?),
+        None => None,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((created_at, updated_at, deleted_at))
 }
 
 impl NoteBackend for FilesystemBackend {
-    /// Creates a new note by writing it to the filesystem as a file
+    /// Creates a new note by atomically writing it to the filesystem as a file
     ///
     /// # Errors
     ///
     /// Returns:
-    /// - `BackendError::Duplicate` if a note with the same ID already exists
+    /// - `BackendError::Duplicate` if a note with the same ID already exists, even if another process just created it
     /// - `BackendError::FileCreationError` if the file cannot be created
     /// - `BackendError::FileWriteError` if writing to the file fails
-    fn create(&self, note: Note) -> Result<u16> {
-        let path = self.note_path(note.id);
-        if path.exists() {
-            return Err(NoteError::Backend(BackendError::Duplicate));
-        }
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn create(&self, note: Note) -> Result<NoteId> {
+        self.with_lock(|| {
+            let path = self.note_path(note.id);
+            self.journal(&path)?;
 
-        let mut file = File::create(&path)
-            .map_err(|e| NoteError::Backend(BackendError::FileCreationError(e)))?;
-        trace!("Created file: {}", &path.display());
-        let data = format!("{}\n{}\n{}", note.name, note.owner, note.content);
-        file.write_all(data.as_bytes())
-            .map_err(|e| NoteError::Backend(BackendError::FileWriteError(e)))?;
-        trace!("Wrote data to file:\n{}", &data);
-        Ok(note.id)
+            let data = format_note(&note);
+            create_atomically(&path, data.as_bytes())?;
+            trace!("Wrote data to file {}:\n{}", &path.display(), &data);
+            Ok(note.id)
+        })
     }
 
     /// Reads a note file by ID and returns the full note
@@ -93,39 +847,18 @@ impl NoteBackend for FilesystemBackend {
     /// Returns:
     /// - `BackendError::NoteNotFound` if the note file does not exist
     /// - `BackendError::FileReadError` if the file cannot be read
-    /// - `BackendError::NoteCorrupted` if the file does not contain at least three lines (name, owner and 1 line of content)
-    fn read(&self, id: u16) -> Result<Note> {
-        let path = self.note_path(id);
-        let mut file =
-            File::open(&path).map_err(|_| NoteError::Backend(BackendError::NoteNotFound(id)))?;
-        trace!("Opened file for note #{} for reading", &id);
-
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| NoteError::Backend(BackendError::FileReadError(e)))?;
-
-        let mut lines = contents.lines();
-        let name = lines
-            .next()
-            .ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
-        let owner = lines
-            .next()
-            .ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
-        let content = lines.collect::<Vec<&str>>().join("\n");
+    /// - `BackendError::NoteCorrupted` if the file does not contain a name, an owner and 1 line of content
+    fn read(&self, id: NoteId) -> Result<Note> {
+        let note = self.read_note_file(id)?;
 
-        if content.trim().is_empty() {
+        if note.content.trim().is_empty() {
             return Err(NoteError::Backend(BackendError::NoteCorrupted));
         }
 
-        Ok(Note {
-            id,
-            name: name.to_string(),
-            owner: owner.to_string(),
-            content,
-        })
+        Ok(note)
     }
 
-    /// Reads only the ID, name, and owner of a note by ID
+    /// Reads only the ID, name, owner and timestamps of a note by ID
     ///
     /// # Errors
     ///
@@ -133,96 +866,915 @@ impl NoteBackend for FilesystemBackend {
     /// - `BackendError::NoteNotFound` if the note file does not exist
     /// - `BackendError::FileReadError` if the file cannot be read
     /// - `BackendError::NoteCorrupted` if the file does not contain at least two lines (name and owner)
-    fn read_partial(&self, id: u16) -> Result<PartialNote> {
-        let path = self.note_path(id);
-        let mut file =
-            File::open(&path).map_err(|_| NoteError::Backend(BackendError::NoteNotFound(id)))?;
-
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| NoteError::Backend(BackendError::FileReadError(e)))?;
-
-        let mut lines = contents.lines();
-        let name = lines
-            .next()
-            .ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
-        let owner = lines
-            .next()
-            .ok_or(NoteError::Backend(BackendError::NoteCorrupted))?;
+    fn read_partial(&self, id: NoteId) -> Result<PartialNote> {
+        let note = self.read_note_file(id)?;
 
         Ok(PartialNote {
             id,
-            name: name.to_string(),
-            owner: owner.to_string(),
+            name: note.name,
+            owner: note.owner,
+            created_at: note.created_at,
+            updated_at: note.updated_at,
+            deleted_at: note.deleted_at,
         })
     }
 
-    /// Updates an existing note file with new name, owner, and content
+    /// Finds notes by name by reading every note file, and the grants file of those not owned by one of `owners`
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::DirectoryReadError` if the notes directory cannot be read
+    ///
+    /// # Note
+    ///
+    /// Silently skips corrupt or unreadable notes and grants
+    fn find_by_name(
+        &self,
+        name: &str,
+        owners: &[String],
+        grantee: &str,
+    ) -> Result<Vec<PartialNote>> {
+        let mut notes: Vec<PartialNote> = self
+            .list_files("note")?
+            .iter()
+            .filter_map(|file_path| file_path.file_stem()?.to_str()?.parse::<NoteId>().ok())
+            .filter_map(|id| self.read_partial(id).ok())
+            .filter(|note| {
+                note.name == name
+                    && (owners.contains(&note.owner)
+                        || self
+                            .list_grants(note.id)
+                            .is_ok_and(|grants| grants.iter().any(|grant| grant.user == grantee)))
+            })
+            .collect();
+        notes.sort_by_key(|note| note.id);
+        Ok(notes)
+    }
+
+    /// Updates an existing note file with new name, owner, content and modification time,
+    /// keeping the creation time and trash state already stored in the file.
+    /// The replaced file is copied to a revision file first, and both are written atomically.
+    /// The revision file is removed again if the note file can't be replaced
     ///
     /// # Errors
     ///
     /// Returns:
     /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::NoteCorrupted` if the existing note file cannot be parsed
     /// - `BackendError::FileCreationError` if the file cannot be created and opened
     /// - `BackendError::FileWriteError` if writing to the file fails
-    fn update(&self, note: Note) -> Result<()> {
-        let path = self.note_path(note.id);
-        if !path.exists() || path.is_dir() {
-            return Err(NoteError::Backend(BackendError::NoteNotFound(note.id)));
-        }
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn update(&self, mut note: Note) -> Result<()> {
+        self.with_lock(|| {
+            let path = self.note_path(note.id);
+            if !path.exists() || path.is_dir() {
+                return Err(NoteError::Backend(BackendError::NoteNotFound(note.id)));
+            }
+            let stored = self.read_note_file(note.id)?;
+            note.created_at = stored.created_at;
+            note.deleted_at = stored.deleted_at;
 
-        let mut file = File::create(&path)
-            .map_err(|e| NoteError::Backend(BackendError::FileCreationError(e)))?;
-        let data = format!("{}\n{}\n{}", note.name, note.owner, note.content);
-        file.write_all(data.as_bytes())
-            .map_err(|e| NoteError::Backend(BackendError::FileWriteError(e)))?;
-        Ok(())
+            // Keep the replaced version as the next revision
+            let revision = self
+                .revision_numbers(note.id)?
+                .last()
+                .map_or(1, |revision| revision + 1);
+            let revision_path = self.revision_path(note.id, revision);
+            self.journal(&revision_path)?;
+            let stored_data =
+                fs::read(&path).map_err(|e| NoteError::Backend(BackendError::FileReadError(e)))?;
+            write_atomically(&revision_path, &stored_data)?;
+            trace!("Saved revision {revision} of note #{}", note.id);
+
+            self.journal(&path)?;
+            let result = write_atomically(&path, format_note(&note).as_bytes());
+            // Don't leave a revision behind for an update that never happened
+            if result.is_err() && fs::read(&path).is_ok_and(|data| data == stored_data) {
+                let _ = fs::remove_file(&revision_path);
+            }
+            result
+        })
     }
 
-    /// Deletes a note file by ID
+    /// Deletes a note file by ID, along with its tags, revisions, grants and references,
+    /// and syncs the notes directory so the deletion survives a crash
     ///
     /// # Errors
     ///
     /// Returns:
     /// - `BackenDError::PermissionDenied` if the file can't be deleted due to missing privileges
     /// - `BackendError::NoteNotFound` if the file does not exist or the path is a directory
+    /// - `BackendError::FileWriteError` if removing the other files or syncing the directory fails
     /// - `BackendError::Other` as a catch-all for other unexpected errors
-    fn delete(&self, id: u16) -> Result<()> {
-        use std::io::ErrorKind;
-
-        let path = self.note_path(id);
-        fs::remove_file(&path)
-            .map_err(|e| match e.kind() {
-                ErrorKind::PermissionDenied => BackendError::PermissionDenied,
-                ErrorKind::IsADirectory | ErrorKind::NotFound => BackendError::NoteNotFound(id),
-                _ => BackendError::Other(anyhow::anyhow!("Filesystem error: {:?}", e)),
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn delete(&self, id: NoteId) -> Result<()> {
+        self.with_lock(|| {
+            let path = self.note_path(id);
+            self.journal(&path)?;
+            fs::remove_file(&path)
+                .map_err(|e| match e.kind() {
+                    ErrorKind::PermissionDenied => BackendError::PermissionDenied,
+                    ErrorKind::IsADirectory | ErrorKind::NotFound => BackendError::NoteNotFound(id),
+                    _ => BackendError::Other(anyhow::anyhow!("Filesystem error: {:?}", e)),
+                })
+                .map_err(NoteError::Backend)?;
+
+            for revision in self.revision_numbers(id)? {
+                let revision_path = self.revision_path(id, revision);
+                self.journal(&revision_path)?;
+                fs::remove_file(&revision_path)
+                    .map_err(|e| NoteError::Backend(BackendError::FileWriteError(e)))?;
+            }
+
+            self.write_grants(id, &[])?;
+            if let Some(mut links) = self.read_links()?
+                && links.remove(&id).is_some()
+            {
+                self.write_links(&links)?;
+            }
+            if let Some(mut name_links) = self.read_name_links()?
+                && name_links.remove(&id).is_some()
+            {
+                self.write_name_links(&name_links)?;
+            }
+            self.set_tags(id, &[])?;
+            self.sync_directory()
+                .map_err(|e| NoteError::Backend(BackendError::FileWriteError(e)))
+        })
+    }
+
+    /// Rewrites a note file with the given deletion time, without saving a revision
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::NoteCorrupted` if the existing note file cannot be parsed
+    /// - `BackendError::FileCreationError` if the file cannot be created and opened
+    /// - `BackendError::FileWriteError` if writing to the file fails
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn set_deleted(&self, id: NoteId, deleted_at: Option<DateTime<Utc>>) -> Result<()> {
+        self.with_lock(|| {
+            let mut note = self.read_note_file(id)?;
+            note.deleted_at = deleted_at;
+
+            let path = self.note_path(id);
+            self.journal(&path)?;
+            write_atomically(&path, format_note(&note).as_bytes())?;
+            trace!("Set trash state of note #{id}: {deleted_at:?}");
+            Ok(())
+        })
+    }
+
+    /// Adds or replaces the line of a user in the grants file of a note
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::FileReadError` if the existing grants file cannot be read
+    /// - `BackendError::FileCreationError` if the grants file cannot be created
+    /// - `BackendError::FileWriteError` if writing to the grants file fails
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn set_grant(&self, id: NoteId, user: &str, permission: Permission) -> Result<()> {
+        self.with_lock(|| {
+            if !self.note_path(id).is_file() {
+                return Err(NoteError::Backend(BackendError::NoteNotFound(id)));
+            }
+
+            let mut grants = self.list_grants(id)?;
+            grants.retain(|grant| grant.user != user);
+            grants.push(Grant {
+                note_id: id,
+                user: user.to_string(),
+                permission,
+            });
+            grants.sort_by(|a, b| a.user.cmp(&b.user));
+            self.write_grants(id, &grants)
+        })
+    }
+
+    /// Removes the line of a user from the grants file of a note
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::GrantNotFound` if the user has no grant on the note
+    /// - `BackendError::FileReadError` if the grants file cannot be read
+    /// - `BackendError::FileWriteError` if writing to or removing the grants file fails
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn remove_grant(&self, id: NoteId, user: &str) -> Result<()> {
+        self.with_lock(|| {
+            let mut grants = self.list_grants(id)?;
+            let count = grants.len();
+            grants.retain(|grant| grant.user != user);
+            if grants.len() == count {
+                return Err(NoteError::Backend(BackendError::GrantNotFound {
+                    id,
+                    user: user.to_string(),
+                }));
+            }
+            self.write_grants(id, &grants)
+        })
+    }
+
+    /// Reads the grants file of a note, returning no grants if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::FileReadError` if the grants file exists but cannot be read
+    ///
+    /// # Note
+    ///
+    /// Silently skips malformed lines
+    fn list_grants(&self, id: NoteId) -> Result<Vec<Grant>> {
+        let contents = match fs::read_to_string(self.grants_path(id)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(NoteError::Backend(BackendError::FileReadError(e))),
+        };
+
+        let mut grants: Vec<Grant> = contents
+            .lines()
+            .filter_map(|line| {
+                let (permission, user) = line.split_once(' ')?;
+                Some(Grant {
+                    note_id: id,
+                    user: user.to_string(),
+                    permission: Permission::parse(permission)?,
+                })
             })
-            .map_err(NoteError::Backend)
+            .collect();
+        grants.sort_by(|a, b| a.user.cmp(&b.user));
+        Ok(grants)
     }
 
-    /// Lists all notes in the filesystem by parsing their filenames and reading partial metadata
+    /// Lists notes in the filesystem by parsing their filenames and reading partial metadata.
+    /// Filtering by tag and trash state, sorting and pagination are done in memory after reading every note
     ///
     /// # Errors
     ///
-    /// Returns an error if reading the list of note files fails
+    /// Returns an error if reading the list of note or tags files fails
     ///
     /// # Note
     ///
-    /// Silently skips corrupt or unreadable notes
-    fn list(&self) -> Result<Vec<PartialNote>> {
+    /// Silently skips corrupt or unreadable notes and tags files
+    fn list(&self, query: &ListQuery) -> Result<Vec<PartialNote>> {
         let mut notes = Vec::new();
 
-        for file_path in self.list_note_files()? {
-            if let Some(stem) = file_path.file_stem().and_then(|s| s.to_str()) {
-                if let Ok(id) = stem.parse::<u16>() {
-                    if let Ok(note) = self.read_partial(id) {
-                        notes.push(note);
+        // Only notes with tags have a tags file, so start from those when filtering by tag
+        let extension = if query.tag.is_some() { "tags" } else { "note" };
+        for file_path in self.list_files(extension)? {
+            if let Some(stem) = file_path.file_stem().and_then(|s| s.to_str())
+                && let Ok(id) = stem.parse::<NoteId>()
+                && query
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| self.read_tags(id).is_ok_and(|tags| tags.contains(tag)))
+                && let Ok(note) = self.read_partial(id)
+                && query.trash.matches(note.deleted_at)
+            {
+                notes.push(note);
+            }
+        }
+
+        Ok(query.sort_and_paginate(notes))
+    }
+
+    /// Returns the IDs of all note files, whether or not they can be parsed
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::DirectoryReadError` if the directory cannot be read
+    fn list_ids(&self) -> Result<Vec<NoteId>> {
+        let mut ids: Vec<NoteId> = self
+            .list_files("note")?
+            .iter()
+            .filter_map(|file_path| file_path.file_stem()?.to_str()?.parse().ok())
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Copies a note file to the `quarantine` directory, named after the time it was quarantined,
+    /// and deletes the note along with its other files
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::DirectoryCreationError` if the quarantine directory cannot be created
+    /// - `BackendError::FileWriteError` if the note file cannot be copied or its other files removed
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn quarantine(&self, id: NoteId) -> Result<()> {
+        self.with_lock(|| {
+            let path = self.note_path(id);
+            if !path.is_file() {
+                return Err(NoteError::Backend(BackendError::NoteNotFound(id)));
+            }
+
+            let quarantine_path = self.quarantine_path();
+            fs::create_dir_all(&quarantine_path)
+                .map_err(|e| NoteError::Backend(BackendError::DirectoryCreationError(e)))?;
+            fs::copy(
+                &path,
+                quarantine_path.join(format!("{id:05}.{}.note", Utc::now().timestamp())),
+            )
+            .map_err(|e| NoteError::Backend(BackendError::FileWriteError(e)))?;
+
+            self.delete(id)?;
+            trace!("Quarantined note #{id}");
+            Ok(())
+        })
+    }
+
+    /// Replaces the tags of a note by rewriting its tags file, or removing it if there are no tags
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::FileCreationError` if the tags file cannot be created
+    /// - `BackendError::FileWriteError` if writing to or removing the tags file fails
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn set_tags(&self, id: NoteId, tags: &[String]) -> Result<()> {
+        self.with_lock(|| {
+            let path = self.tags_path(id);
+            self.journal(&path)?;
+            if tags.is_empty() {
+                return match fs::remove_file(&path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => {
+                        Err(NoteError::Backend(BackendError::FileWriteError(e)))
                     }
-                }
+                    _ => Ok(()),
+                };
+            }
+
+            if !self.note_path(id).is_file() {
+                return Err(NoteError::Backend(BackendError::NoteNotFound(id)));
+            }
+
+            write_atomically(&path, tags.join("\n").as_bytes())?;
+            trace!("Wrote tags of note #{id} to file: {tags:?}");
+            Ok(())
+        })
+    }
+
+    /// Reads the tags file of a note, returning no tags if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::FileReadError` if the tags file exists but cannot be read
+    fn read_tags(&self, id: NoteId) -> Result<Vec<String>> {
+        let contents = match fs::read_to_string(self.tags_path(id)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(NoteError::Backend(BackendError::FileReadError(e))),
+        };
+
+        let mut tags: Vec<String> = contents.lines().map(str::to_string).collect();
+        tags.sort();
+        tags.dedup();
+        Ok(tags)
+    }
+
+    /// Replaces the lines of a note in the reference index files. Does nothing while either index file doesn't exist,
+    /// since the index is filled from scratch once it is marked
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note file does not exist
+    /// - `BackendError::FileReadError` if the index file cannot be read
+    /// - `BackendError::FileCreationError` if the index file cannot be created
+    /// - `BackendError::FileWriteError` if writing to the index file fails
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn set_links(&self, id: NoteId, targets: &[NoteId], names: &[String]) -> Result<()> {
+        self.with_lock(|| {
+            if !self.note_path(id).is_file() {
+                return Err(NoteError::Backend(BackendError::NoteNotFound(id)));
+            }
+            let (Some(mut links), Some(mut name_links)) =
+                (self.read_links()?, self.read_name_links()?)
+            else {
+                return Ok(());
+            };
+
+            let mut targets = targets.to_vec();
+            targets.sort_unstable();
+            targets.dedup();
+            let mut names = names.to_vec();
+            names.sort_unstable();
+            names.dedup();
+            trace!("Set references of note #{id}: {targets:?}, by name: {names:?}");
+            links.insert(id, targets);
+            self.write_links(&links)?;
+            name_links.insert(id, names);
+            self.write_name_links(&name_links)
+        })
+    }
+
+    /// Finds the notes referencing a note by reading the reference index file
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::FileReadError` if the index file exists but cannot be read
+    fn backlinks(&self, id: NoteId) -> Result<Vec<NoteId>> {
+        Ok(self
+            .read_links()?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, targets)| targets.contains(&id))
+            .map(|(source, _)| source)
+            .collect())
+    }
+
+    /// Finds the notes referencing any of the names by reading the index file of references by name
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::FileReadError` if the index file exists but cannot be read
+    fn name_backlinks(&self, names: &[&str]) -> Result<Vec<NoteId>> {
+        Ok(self
+            .read_name_links()?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, referenced)| referenced.iter().any(|name| names.contains(&name.as_str())))
+            .map(|(source, _)| source)
+            .collect())
+    }
+
+    /// Checks if both reference index files exist. Notes directories written before references by name were
+    /// indexed only have the first one
+    ///
+    /// # Errors
+    ///
+    /// Never fails for this backend
+    fn links_indexed(&self) -> Result<bool> {
+        Ok(self.links_path().is_file() && self.name_links_path().is_file())
+    }
+
+    /// Creates the empty reference index files that don't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::FileCreationError` if the index file cannot be created
+    /// - `BackendError::FileWriteError` if writing to the index file fails
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    fn mark_links_indexed(&self) -> Result<()> {
+        self.with_lock(|| {
+            if !self.links_path().is_file() {
+                self.write_links(&BTreeMap::new())?;
+            }
+            if !self.name_links_path().is_file() {
+                self.write_name_links(&BTreeMap::new())?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Searches all notes by streaming the name and content of each note file line by line, for notes where every
+    /// term starts a word, ignoring case, like the prefix queries of the `SQLite` search index
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the list of note files fails
+    ///
+    /// # Note
+    ///
+    /// Silently skips corrupt or unreadable notes
+    fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let terms = search_terms(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut results = Vec::new();
+
+        for file_path in self.list_files("note")? {
+            if let Some(stem) = file_path.file_stem().and_then(|s| s.to_str())
+                && let Ok(id) = stem.parse::<NoteId>()
+                && let Ok(Some(snippet)) = self.search_note_file(id, &terms)
+                && let Ok(note) = self.read_partial(id)
+            {
+                results.push(SearchResult { note, snippet });
             }
         }
 
-        notes.sort_by_key(|n| n.id);
-        Ok(notes)
+        results.sort_by_key(|r| r.note.id);
+        Ok(results)
+    }
+
+    /// Lists all revision files of a note, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the list of revision files fails
+    ///
+    /// # Note
+    ///
+    /// Silently skips corrupt or unreadable revisions
+    fn list_revisions(&self, id: NoteId) -> Result<Vec<Revision>> {
+        Ok(self
+            .revision_numbers(id)?
+            .into_iter()
+            .filter_map(|revision| self.read_revision(id, revision).ok())
+            .collect())
+    }
+
+    /// Reads a revision file of a note
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::RevisionNotFound` if the revision file does not exist
+    /// - `BackendError::FileReadError` if the file cannot be read
+    /// - `BackendError::NoteCorrupted` if the file does not contain at least two lines (name and owner)
+    fn read_revision(&self, id: NoteId, revision: u32) -> Result<Revision> {
+        let file = File::open(self.revision_path(id, revision))
+            .map_err(|_| NoteError::Backend(BackendError::RevisionNotFound { id, revision }))?;
+        let note = parse_note_file(file, id)?;
+
+        Ok(Revision {
+            note_id: id,
+            revision,
+            name: note.name,
+            content: note.content,
+            updated_at: note.updated_at,
+        })
+    }
+
+    /// Locks the notes directory and starts journaling modified files
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::TransactionAlreadyActive` if a transaction is already active
+    /// - `BackendError::LockError` if the notes directory cannot be locked
+    /// - `BackendError::JournalError` if a leftover journal cannot be rolled back or a new one can't be created
+    fn begin(&self) -> Result<()> {
+        if self.lock.borrow().is_some() {
+            return Err(NoteError::Backend(BackendError::TransactionAlreadyActive));
+        }
+
+        let lock = self.lock_directory()?;
+        // A journal left behind by a crashed process belongs to a transaction that never committed
+        self.recover_journal()?;
+        fs::create_dir(self.journal_path())
+            .map_err(|e| NoteError::Backend(BackendError::JournalError(e)))?;
+
+        *self.lock.borrow_mut() = Some(lock);
+        trace!("Began transaction");
+        Ok(())
+    }
+
+    /// Discards the journal and unlocks the notes directory
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoActiveTransaction` if no transaction is active
+    /// - `BackendError::JournalError` if the journal cannot be discarded
+    fn commit(&self) -> Result<()> {
+        let Some(_lock) = self.lock.borrow_mut().take() else {
+            return Err(NoteError::Backend(BackendError::NoActiveTransaction));
+        };
+
+        // Renaming is atomic, so a crash can't leave a half-deleted journal that would be rolled back
+        let committed_path = self.committed_journal_path();
+        fs::rename(self.journal_path(), &committed_path)
+            .and_then(|()| fs::remove_dir_all(&committed_path))
+            .map_err(|e| NoteError::Backend(BackendError::JournalError(e)))?;
+        trace!("Committed transaction");
+        Ok(())
+    }
+
+    /// Restores all files from the journal and unlocks the notes directory
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoActiveTransaction` if no transaction is active
+    /// - `BackendError::JournalError` if the files cannot be restored
+    fn rollback(&self) -> Result<()> {
+        let Some(_lock) = self.lock.borrow_mut().take() else {
+            return Err(NoteError::Backend(BackendError::NoActiveTransaction));
+        };
+
+        self.recover_journal()?;
+        trace!("Rolled back transaction");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Steps before the temporary file is moved into place, where a failure must leave the old file in place
+    const STEPS_BEFORE_MOVE: [WriteStep; 4] = [
+        WriteStep::Create,
+        WriteStep::Write,
+        WriteStep::Sync,
+        WriteStep::Move,
+    ];
+
+    fn backend() -> (TempDir, FilesystemBackend) {
+        let dir = TempDir::new().unwrap();
+        let backend = FilesystemBackend::new(dir.path().to_str().unwrap()).unwrap();
+        (dir, backend)
+    }
+
+    fn note(id: NoteId, content: &str) -> Note {
+        let now = Utc::now().trunc_subsecs(0);
+        Note {
+            id,
+            owner: "ctf".to_string(),
+            name: format!("Note {id}"),
+            content: content.to_string(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        }
+    }
+
+    /// Runs an operation with a failure injected right before the given step of every atomic write
+    /// of a file with the given extension
+    fn failing_at<T>(step: WriteStep, extension: &'static str, operation: impl FnOnce() -> T) -> T {
+        FAILING_STEP.set(Some((step, extension)));
+        let result = operation();
+        FAILING_STEP.set(None);
+        result
+    }
+
+    fn temporary_files(dir: &TempDir) -> Vec<PathBuf> {
+        fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tmp"))
+            .collect()
+    }
+
+    #[test]
+    fn failed_create_leaves_no_note() {
+        for step in STEPS_BEFORE_MOVE {
+            let (dir, backend) = backend();
+
+            let result = failing_at(step, "note", || backend.create(note(1, "content")));
+
+            assert!(
+                result.is_err(),
+                "create succeeded despite failing at {step:?}"
+            );
+            assert!(matches!(
+                backend.read(1),
+                Err(NoteError::Backend(BackendError::NoteNotFound(1)))
+            ));
+            assert!(temporary_files(&dir).is_empty());
+        }
+    }
+
+    #[test]
+    fn failed_update_keeps_old_note() {
+        for step in STEPS_BEFORE_MOVE {
+            let (dir, backend) = backend();
+            backend.create(note(1, "old content")).unwrap();
+
+            // Fail replacing the note itself, after its revision has been written
+            let result = failing_at(step, "note", || backend.update(note(1, "new content")));
+
+            assert!(
+                result.is_err(),
+                "update succeeded despite failing at {step:?}"
+            );
+            assert_eq!(backend.read(1).unwrap().content, "old content");
+            assert!(backend.list_revisions(1).unwrap().is_empty());
+            assert!(backend.list_files("rev").unwrap().is_empty());
+            assert!(temporary_files(&dir).is_empty());
+        }
+    }
+
+    #[test]
+    fn failed_rewrite_keeps_old_note() {
+        for step in STEPS_BEFORE_MOVE {
+            let (dir, backend) = backend();
+            backend.create(note(1, "content")).unwrap();
+
+            let result = failing_at(step, "note", || backend.set_deleted(1, Some(Utc::now())));
+
+            assert!(
+                result.is_err(),
+                "set_deleted succeeded despite failing at {step:?}"
+            );
+            let stored = backend.read(1).unwrap();
+            assert_eq!(stored.content, "content");
+            assert_eq!(stored.deleted_at, None);
+            assert!(temporary_files(&dir).is_empty());
+        }
+    }
+
+    #[test]
+    fn failed_directory_sync_keeps_new_note() {
+        let (dir, backend) = backend();
+        backend.create(note(1, "content")).unwrap();
+        let deleted_at = Utc::now().trunc_subsecs(0);
+
+        let result = failing_at(WriteStep::SyncDirectory, "note", || {
+            backend.set_deleted(1, Some(deleted_at))
+        });
+
+        assert!(matches!(
+            result,
+            Err(NoteError::Backend(BackendError::FileWriteError(_)))
+        ));
+        assert_eq!(backend.read(1).unwrap().deleted_at, Some(deleted_at));
+        assert!(temporary_files(&dir).is_empty());
+    }
+
+    #[test]
+    fn create_fails_for_existing_note() {
+        let (_dir, backend) = backend();
+        backend.create(note(1, "first")).unwrap();
+
+        assert!(matches!(
+            backend.create(note(1, "second")),
+            Err(NoteError::Backend(BackendError::Duplicate))
+        ));
+        assert_eq!(backend.read(1).unwrap().content, "first");
+    }
+
+    #[test]
+    fn concurrent_creates_of_same_note_succeed_once() {
+        const WRITERS: usize = 8;
+        let (dir, backend) = backend();
+        let path = dir.path().to_str().unwrap();
+        let barrier = std::sync::Barrier::new(WRITERS);
+
+        let results: Vec<Result<NoteId>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..WRITERS)
+                .map(|writer| {
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        // Each writer has its own backend, like separate processes do
+                        let writer_backend = FilesystemBackend::new(path).unwrap();
+                        barrier.wait();
+                        writer_backend.create(note(1, &format!("writer {writer}")))
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let created: Vec<usize> = (0..WRITERS).filter(|&i| results[i].is_ok()).collect();
+        assert_eq!(created.len(), 1);
+        assert!(results
+            .iter()
+            .filter(|r| r.is_err())
+            .all(|r| matches!(r, Err(NoteError::Backend(BackendError::Duplicate)))));
+        assert_eq!(
+            backend.read(1).unwrap().content,
+            format!("writer {}", created[0])
+        );
+        assert!(temporary_files(&dir).is_empty());
+    }
+
+    /// Fields of a note, for comparing notes
+    fn fields(note: &Note) -> (NoteId, &str, &str, &str, [Option<DateTime<Utc>>; 3]) {
+        (
+            note.id,
+            note.name.as_str(),
+            note.owner.as_str(),
+            note.content.as_str(),
+            [
+                Some(note.created_at),
+                Some(note.updated_at),
+                note.deleted_at,
+            ],
+        )
+    }
+
+    #[test]
+    fn note_file_round_trips() {
+        let (_dir, backend) = backend();
+        let cases = [
+            ("two\nlines", "content"),
+            ("noters-note 2", "windows\r\nline endings\r\n"),
+            ("trailing newlines", "text\n\n"),
+            ("empty content", ""),
+            ("content 3", "name 4\nowner\n"),
+        ];
+
+        for (id, (name, content)) in (1..).zip(cases) {
+            let mut written = note(id, content);
+            written.name = name.to_string();
+            written.deleted_at = Some(written.created_at);
+            backend.create(written.clone()).unwrap();
+            backend.set_deleted(id, written.deleted_at).unwrap();
+
+            // `read` rejects empty content, so the file is parsed directly
+            let stored = backend.read_note_file(id).unwrap();
+            assert_eq!(fields(&stored), fields(&written));
+            assert!(fs::read_to_string(backend.note_path(id))
+                .unwrap()
+                .starts_with("noters-note 2\n"));
+        }
+    }
+
+    #[test]
+    fn legacy_note_is_migrated_on_write() {
+        let (dir, backend) = backend();
+        fs::write(
+            dir.path().join("00001.note"),
+            "Legacy\nctf\n1755446400 1755450000\nfirst\r\nsecond",
+        )
+        .unwrap();
+
+        let legacy = backend.read(1).unwrap();
+        assert_eq!(legacy.name, "Legacy");
+        assert_eq!(legacy.owner, "ctf");
+        assert_eq!(legacy.content, "first\nsecond");
+        assert_eq!(legacy.created_at.timestamp(), 1_755_446_400);
+        assert_eq!(legacy.updated_at.timestamp(), 1_755_450_000);
+        assert_eq!(legacy.deleted_at, None);
+
+        let deleted_at = Utc::now().trunc_subsecs(0);
+        backend.set_deleted(1, Some(deleted_at)).unwrap();
+
+        let migrated = fs::read_to_string(dir.path().join("00001.note")).unwrap();
+        assert!(migrated.starts_with("noters-note 2\n"));
+        let stored = backend.read(1).unwrap();
+        assert_eq!(
+            fields(&stored),
+            fields(&Note {
+                deleted_at: Some(deleted_at),
+                ..legacy
+            })
+        );
+    }
+
+    #[test]
+    fn legacy_note_named_like_header_is_read() {
+        let (dir, backend) = backend();
+        for (id, name) in [
+            (1, "noters-note 3"),
+            (2, "noters-note 2 drafts"),
+            (3, "noters-note x"),
+        ] {
+            fs::write(
+                dir.path().join(format!("{id:05}.note")),
+                format!("{name}\nctf\n1755446400 1755450000\ncontent"),
+            )
+            .unwrap();
+
+            let legacy = backend.read(id).unwrap();
+            assert_eq!(legacy.name, name);
+            assert_eq!(legacy.owner, "ctf");
+            assert_eq!(legacy.content, "content");
+        }
+    }
+
+    #[test]
+    fn search_matches_word_prefixes() {
+        let (dir, backend) = backend();
+        backend
+            .create(note(1, "first line\nsome barbaric text"))
+            .unwrap();
+        fs::write(
+            dir.path().join("00002.note"),
+            "Legacy\nctf\n1755446400 1755450000\nthe Brown fox\r\n",
+        )
+        .unwrap();
+
+        let found = |query| {
+            backend
+                .search(query)
+                .unwrap()
+                .into_iter()
+                .map(|result| (result.note.id, result.snippet))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found("BAR"), vec![(1, "some [barbaric] text".to_string())]);
+        assert_eq!(found("arbar"), vec![]);
+        assert_eq!(found("note 1"), vec![(1, "first line".to_string())]);
+        assert_eq!(found("brown"), vec![(2, "the [Brown] fox".to_string())]);
+        assert_eq!(found("legacy"), vec![(2, "the Brown fox".to_string())]);
+        assert_eq!(found("1755446400"), vec![]);
+    }
+
+    #[test]
+    fn leftover_temporary_file_is_ignored() {
+        let (dir, backend) = backend();
+        backend.create(note(1, "content")).unwrap();
+        // What a crash between writing and renaming leaves behind
+        fs::write(
+            dir.path().join(".00001.note.tmp"),
+            "noters-note 2\nname 4\nNo",
+        )
+        .unwrap();
+        fs::write(dir.path().join(".00002.note.tmp"), "").unwrap();
+
+        assert_eq!(backend.list_ids().unwrap(), vec![1]);
+        assert_eq!(backend.read(1).unwrap().content, "content");
     }
 }
@@ -0,0 +1,660 @@
+use super::{
+    filesystem::write_atomically,
+    memory::{InMemoryBackend, Store},
+    BackendError, Grant, ListQuery, Note, NoteBackend, NoteError, NoteId, PartialNote, Permission,
+    Result, Revision, SearchResult,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, trace};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{cell::Cell, collections::BTreeMap, fs, io::ErrorKind, path::PathBuf};
+
+/// Schema version written to the header of new documents
+const SCHEMA_VERSION: u32 = 2;
+
+/// First schema version where notes list the names they reference notes by
+const NAME_LINKS_VERSION: u32 = 2;
+
+/// Stores all notes in a single JSON document, meant to be readable and diffable by humans.
+///
+/// The document is loaded into an `InMemoryBackend` when the backend is created, and written back in full
+/// after every change, or at the end of a transaction. Only one process should use a document at a time
+#[derive(Debug)]
+pub struct JsonBackend {
+    path: PathBuf,
+    memory: InMemoryBackend,
+    /// Whether a transaction is active, so changes are only written when it's committed
+    in_transaction: Cell<bool>,
+    /// Whether the references of every note are stored, which they aren't in documents of older schema versions
+    links_indexed: Cell<bool>,
+}
+
+/// Layout of the JSON document. Notes are ordered by ID, with their tags, grants, references and revisions nested inside
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    notes: Vec<JsonNote>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    quarantined: Vec<JsonNote>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonNote {
+    id: NoteId,
+    name: String,
+    owner: String,
+    content: String,
+    created_at: Timestamp,
+    updated_at: Timestamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deleted_at: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Permission of each user with access, by user
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    grants: BTreeMap<String, String>,
+    /// IDs of the notes this note references
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    links: Vec<NoteId>,
+    /// Names this note references notes by, whether or not they resolve
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    name_links: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<JsonRevision>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonRevision {
+    revision: u32,
+    name: String,
+    content: String,
+    updated_at: Timestamp,
+}
+
+/// Timestamp written as RFC 3339 with a precision of seconds, like `2025-01-31T12:00:00Z`
+#[derive(Clone, Copy)]
+struct Timestamp(DateTime<Utc>);
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_rfc3339_opts(SecondsFormat::Secs, true))
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&text)
+            .map(|time| Self(time.with_timezone(&Utc)))
+            .map_err(|e| D::Error::custom(format!("invalid timestamp '{text}': {e}")))
+    }
+}
+
+impl JsonNote {
+    /// Collects a stored note and everything attached to it
+    fn from_store(store: &Store, note: &Note) -> Self {
+        Self {
+            id: note.id,
+            name: note.name.clone(),
+            owner: note.owner.clone(),
+            content: note.content.clone(),
+            created_at: Timestamp(note.created_at),
+            updated_at: Timestamp(note.updated_at),
+            deleted_at: note.deleted_at.map(Timestamp),
+            tags: store
+                .tags
+                .get(&note.id)
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+            grants: store
+                .grants
+                .get(&note.id)
+                .into_iter()
+                .flatten()
+                .map(|(user, permission)| (user.clone(), permission.as_str().to_string()))
+                .collect(),
+            links: store
+                .links
+                .get(&note.id)
+                .into_iter()
+                .flatten()
+                .copied()
+                .collect(),
+            name_links: store
+                .name_links
+                .get(&note.id)
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+            revisions: store
+                .revisions
+                .get(&note.id)
+                .into_iter()
+                .flatten()
+                .map(|revision| JsonRevision {
+                    revision: revision.revision,
+                    name: revision.name.clone(),
+                    content: revision.content.clone(),
+                    updated_at: Timestamp(revision.updated_at),
+                })
+                .collect(),
+        }
+    }
+
+    /// Splits the note from everything attached to it, adding those to a store
+    fn into_store(self, store: &mut Store) -> Note {
+        let id = self.id;
+        if !self.tags.is_empty() {
+            store.tags.insert(id, self.tags.into_iter().collect());
+        }
+        // Grants with an unknown permission are skipped, like in `SqliteBackend`
+        let grants: BTreeMap<String, Permission> = self
+            .grants
+            .into_iter()
+            .filter_map(|(user, permission)| Some((user, Permission::parse(&permission)?)))
+            .collect();
+        if !grants.is_empty() {
+            store.grants.insert(id, grants);
+        }
+        if !self.links.is_empty() {
+            store.links.insert(id, self.links.into_iter().collect());
+        }
+        if !self.name_links.is_empty() {
+            store
+                .name_links
+                .insert(id, self.name_links.into_iter().collect());
+        }
+        if !self.revisions.is_empty() {
+            let mut revisions: Vec<Revision> = self
+                .revisions
+                .into_iter()
+                .map(|revision| Revision {
+                    note_id: id,
+                    revision: revision.revision,
+                    name: revision.name,
+                    content: revision.content,
+                    updated_at: revision.updated_at.0,
+                })
+                .collect();
+            revisions.sort_by_key(|revision| revision.revision);
+            store.revisions.insert(id, revisions);
+        }
+
+        Note {
+            id,
+            owner: self.owner,
+            name: self.name,
+            content: self.content,
+            created_at: self.created_at.0,
+            updated_at: self.updated_at.0,
+            deleted_at: self.deleted_at.map(|Timestamp(time)| time),
+        }
+    }
+}
+
+impl JsonBackend {
+    /// Creates a new `JsonBackend` by loading the JSON document at the given path,
+    /// or by writing an empty document if there is none
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::FileReadError` if the document cannot be read
+    /// - `BackendError::InvalidDocument` if the document isn't valid JSON, is missing fields or has duplicate note IDs
+    /// - `BackendError::UnsupportedSchemaVersion` if the document was written by a newer version of noters
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if an empty document cannot be written
+    pub fn new(path: &str) -> Result<Self> {
+        let backend = Self {
+            path: PathBuf::from(path),
+            memory: InMemoryBackend::new(),
+            in_transaction: Cell::new(false),
+            links_indexed: Cell::new(true),
+        };
+
+        let text = match fs::read_to_string(&backend.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                backend.save()?;
+                debug!("Created notes document: {path}");
+                return Ok(backend);
+            }
+            Err(e) => return Err(NoteError::Backend(BackendError::FileReadError(e))),
+        };
+
+        let document: Document = serde_json::from_str(&text)
+            .map_err(|e| NoteError::Backend(BackendError::InvalidDocument(e)))?;
+        if document.version > SCHEMA_VERSION {
+            return Err(NoteError::Backend(BackendError::UnsupportedSchemaVersion(
+                document.version,
+            )));
+        }
+
+        let mut store = Store::default();
+        for note in document.notes {
+            let id = note.id;
+            let note = note.into_store(&mut store);
+            if store.notes.insert(id, note).is_some() {
+                return Err(NoteError::Backend(BackendError::InvalidDocument(
+                    serde_json::Error::custom(format!("duplicate note ID {id}")),
+                )));
+            }
+        }
+        store.quarantined = document
+            .quarantined
+            .into_iter()
+            .map(|note| note.into_store(&mut Store::default()))
+            .collect();
+        debug!("Loaded {} notes from document: {path}", store.notes.len());
+
+        Ok(Self {
+            memory: InMemoryBackend::from_store(store),
+            links_indexed: Cell::new(document.version >= NAME_LINKS_VERSION),
+            ..backend
+        })
+    }
+
+    /// Writes the whole document atomically like the filesystem backend writes notes,
+    /// so a crash leaves either the old or the new document, never a truncated one
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::FileCreationError` if the temporary file cannot be created
+    /// - `BackendError::FileWriteError` if the document cannot be written, flushed or moved into place
+    fn save(&self) -> Result<()> {
+        let json = {
+            let store = self.memory.store();
+            let document = Document {
+                version: SCHEMA_VERSION,
+                notes: store
+                    .notes
+                    .values()
+                    .map(|note| JsonNote::from_store(&store, note))
+                    .collect(),
+                quarantined: store
+                    .quarantined
+                    .iter()
+                    .map(|note| JsonNote::from_store(&Store::default(), note))
+                    .collect(),
+            };
+            serde_json::to_string_pretty(&document)
+                .map_err(|e| NoteError::Backend(BackendError::Other(e.into())))?
+        };
+
+        write_atomically(&self.path, format!("{json}\n").as_bytes())?;
+        trace!("Saved notes document: {}", self.path.display());
+        Ok(())
+    }
+
+    /// Applies a change to the notes in memory and saves the document. Outside of a transaction, the change
+    /// is undone if the document can't be saved, so memory never differs from the file
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the change, or of saving the document
+    fn change<T>(&self, operation: impl FnOnce(&InMemoryBackend) -> Result<T>) -> Result<T> {
+        if self.in_transaction.get() {
+            return operation(&self.memory);
+        }
+
+        self.memory.begin()?;
+        let result = operation(&self.memory).and_then(|value| self.save().map(|()| value));
+        if result.is_ok() {
+            self.memory.commit()?;
+        } else {
+            self.memory.rollback()?;
+        }
+        result
+    }
+}
+
+impl NoteBackend for JsonBackend {
+    /// Adds a new note to the document
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::Duplicate` if a note with the same ID already exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn create(&self, note: Note) -> Result<NoteId> {
+        self.change(|memory| memory.create(note))
+    }
+
+    /// Reads a note from the loaded document
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn read(&self, id: NoteId) -> Result<Note> {
+        self.memory.read(id)
+    }
+
+    /// Reads a note without its content from the loaded document
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn read_partial(&self, id: NoteId) -> Result<PartialNote> {
+        self.memory.read_partial(id)
+    }
+
+    /// Looks up partial notes by name, sorted by ID
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn find_by_name(
+        &self,
+        name: &str,
+        owners: &[String],
+        grantee: &str,
+    ) -> Result<Vec<PartialNote>> {
+        self.memory.find_by_name(name, owners, grantee)
+    }
+
+    /// Replaces the name, owner, content and modification time of a note,
+    /// saving the replaced version as a new revision
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn update(&self, note: Note) -> Result<()> {
+        self.change(|memory| memory.update(note))
+    }
+
+    /// Deletes a note along with its tags, revisions, grants and references
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn delete(&self, id: NoteId) -> Result<()> {
+        self.change(|memory| memory.delete(id))
+    }
+
+    /// Sets or clears the deletion time of a note. Doesn't create a revision.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn set_deleted(&self, id: NoteId, deleted_at: Option<DateTime<Utc>>) -> Result<()> {
+        self.change(|memory| memory.set_deleted(id, deleted_at))
+    }
+
+    /// Inserts or replaces the grant of a user on a note
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn set_grant(&self, id: NoteId, user: &str, permission: Permission) -> Result<()> {
+        self.change(|memory| memory.set_grant(id, user, permission))
+    }
+
+    /// Removes the grant of a user on a note
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::GrantNotFound` if the user has no grant on the note
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn remove_grant(&self, id: NoteId, user: &str) -> Result<()> {
+        self.change(|memory| memory.remove_grant(id, user))
+    }
+
+    /// Returns the grants on a note, sorted by user
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list_grants(&self, id: NoteId) -> Result<Vec<Grant>> {
+        self.memory.list_grants(id)
+    }
+
+    /// Returns notes filtered by tag and trash state, sorted and paginated by the query
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list(&self, query: &ListQuery) -> Result<Vec<PartialNote>> {
+        self.memory.list(query)
+    }
+
+    /// Returns the IDs of all notes, sorted
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list_ids(&self) -> Result<Vec<NoteId>> {
+        self.memory.list_ids()
+    }
+
+    /// Moves a note to the `quarantined` list of the document, without its tags, grants, references and revisions
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn quarantine(&self, id: NoteId) -> Result<()> {
+        self.change(|memory| memory.quarantine(id))
+    }
+
+    /// Replaces the tags of a note. Duplicates are ignored
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn set_tags(&self, id: NoteId, tags: &[String]) -> Result<()> {
+        self.change(|memory| memory.set_tags(id, tags))
+    }
+
+    /// Returns the tags of a note, sorted alphabetically
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn read_tags(&self, id: NoteId) -> Result<Vec<String>> {
+        self.memory.read_tags(id)
+    }
+
+    /// Replaces the notes and names a note references. Duplicates are ignored
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn set_links(&self, id: NoteId, targets: &[NoteId], names: &[String]) -> Result<()> {
+        self.change(|memory| memory.set_links(id, targets, names))
+    }
+
+    /// Returns the notes referencing a note, sorted by ID
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn backlinks(&self, id: NoteId) -> Result<Vec<NoteId>> {
+        self.memory.backlinks(id)
+    }
+
+    /// Returns the notes referencing any of the names, sorted by ID
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn name_backlinks(&self, names: &[&str]) -> Result<Vec<NoteId>> {
+        self.memory.name_backlinks(names)
+    }
+
+    /// References are stored in the document as notes are written, so the index is complete
+    /// unless the document was written before names were stored
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn links_indexed(&self) -> Result<bool> {
+        Ok(self.links_indexed.get())
+    }
+
+    /// Marks the references as complete, which the document says by its schema version once saved
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn mark_links_indexed(&self) -> Result<()> {
+        self.links_indexed.set(true);
+        Ok(())
+    }
+
+    /// Searches note names and contents for notes where every term starts a word, ignoring case
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        self.memory.search(query)
+    }
+
+    /// Returns all revisions of a note, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list_revisions(&self, id: NoteId) -> Result<Vec<Revision>> {
+        self.memory.list_revisions(id)
+    }
+
+    /// Reads a single revision of a note
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::RevisionNotFound` if the note has no such revision
+    fn read_revision(&self, id: NoteId, revision: u32) -> Result<Revision> {
+        self.memory.read_revision(id, revision)
+    }
+
+    /// Starts a transaction, holding back changes from the document until it's committed
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::TransactionAlreadyActive` if a transaction is already active
+    fn begin(&self) -> Result<()> {
+        self.memory.begin()?;
+        self.in_transaction.set(true);
+        Ok(())
+    }
+
+    /// Saves the document with all changes made in the transaction. If saving fails,
+    /// the transaction stays active so it can be rolled back
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoActiveTransaction` if no transaction is active
+    /// - `BackendError::FileCreationError` or `BackendError::FileWriteError` if the document cannot be saved
+    fn commit(&self) -> Result<()> {
+        if !self.in_transaction.get() {
+            return Err(NoteError::Backend(BackendError::NoActiveTransaction));
+        }
+        self.save()?;
+        self.memory.commit()?;
+        self.in_transaction.set(false);
+        Ok(())
+    }
+
+    /// Discards all changes made in the transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoActiveTransaction` if no transaction is active
+    fn rollback(&self) -> Result<()> {
+        self.memory.rollback()?;
+        self.in_transaction.set(false);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::SubsecRound;
+    use tempfile::TempDir;
+
+    fn note(id: NoteId, content: &str) -> Note {
+        let now = Utc::now().trunc_subsecs(0);
+        Note {
+            id,
+            owner: "ctf".to_string(),
+            name: format!("Note {id}"),
+            content: content.to_string(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn saved_notes_are_reloaded() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notes.json");
+        let path = path.to_str().unwrap();
+        let deleted_at = Utc::now().trunc_subsecs(0);
+        {
+            let backend = JsonBackend::new(path).unwrap();
+            backend.create(note(1, "first\r\nline\n")).unwrap();
+            backend.create(note(2, "see [[1]] and [[Foo]]")).unwrap();
+            backend.update(note(1, "changed")).unwrap();
+            backend
+                .set_tags(1, &["a".to_string(), "b".to_string()])
+                .unwrap();
+            backend.set_grant(1, "alice", Permission::Write).unwrap();
+            backend.set_links(2, &[1], &["Foo".to_string()]).unwrap();
+            backend.set_deleted(2, Some(deleted_at)).unwrap();
+
+            // Changes of a rolled back transaction are never saved
+            backend.begin().unwrap();
+            backend.create(note(3, "discarded")).unwrap();
+            backend.rollback().unwrap();
+        }
+
+        let backend = JsonBackend::new(path).unwrap();
+        assert_eq!(backend.list_ids().unwrap(), vec![1, 2]);
+        assert_eq!(backend.read(1).unwrap().content, "changed");
+        assert_eq!(backend.read(2).unwrap().deleted_at, Some(deleted_at));
+        assert_eq!(backend.read_tags(1).unwrap(), ["a", "b"]);
+        let grants = backend.list_grants(1).unwrap();
+        assert_eq!(grants.len(), 1);
+        assert_eq!(grants[0].user, "alice");
+        assert_eq!(grants[0].permission, Permission::Write);
+        assert_eq!(backend.backlinks(1).unwrap(), vec![2]);
+        assert_eq!(backend.name_backlinks(&["Foo"]).unwrap(), vec![2]);
+        assert!(backend.links_indexed().unwrap());
+        let revisions = backend.list_revisions(1).unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].content, "first\r\nline\n");
+    }
+
+    #[test]
+    fn documents_without_name_references_are_indexed_again() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notes.json");
+        fs::write(&path, r#"{"version": 1, "notes": []}"#).unwrap();
+
+        let backend = JsonBackend::new(path.to_str().unwrap()).unwrap();
+        assert!(!backend.links_indexed().unwrap());
+        backend.mark_links_indexed().unwrap();
+        assert!(backend.links_indexed().unwrap());
+    }
+}
@@ -0,0 +1,516 @@
+use super::{
+    search_terms, BackendError, Grant, LineSearch, ListQuery, Note, NoteBackend, NoteError, NoteId,
+    PartialNote, Permission, Result, Revision, SearchResult,
+};
+use chrono::{DateTime, SubsecRound, Utc};
+use log::trace;
+use std::{
+    cell::{Ref, RefCell},
+    collections::{BTreeMap, BTreeSet},
+};
+
+/// Everything stored by an `InMemoryBackend`, cloned as a whole to support transactions
+#[derive(Debug, Default, Clone)]
+pub(super) struct Store {
+    pub(super) notes: BTreeMap<NoteId, Note>,
+    pub(super) tags: BTreeMap<NoteId, BTreeSet<String>>,
+    /// Revisions of each note, oldest first
+    pub(super) revisions: BTreeMap<NoteId, Vec<Revision>>,
+    pub(super) grants: BTreeMap<NoteId, BTreeMap<String, Permission>>,
+    pub(super) links: BTreeMap<NoteId, BTreeSet<NoteId>>,
+    /// Names each note references notes by
+    pub(super) name_links: BTreeMap<NoteId, BTreeSet<String>>,
+    /// Notes moved aside by `quarantine`, kept until the backend is dropped
+    pub(super) quarantined: Vec<Note>,
+}
+
+impl Store {
+    /// Looks up a note, failing with `NoteNotFound` like a missing row in `SQLite` does
+    fn note(&self, id: NoteId) -> Result<&Note> {
+        self.notes
+            .get(&id)
+            .ok_or(NoteError::Backend(BackendError::NoteNotFound(id)))
+    }
+}
+
+/// Keeps notes in memory only, for tests and throwaway sessions. Nothing is saved when the backend is dropped.
+///
+/// Behaves like `SqliteBackend`, down to timestamps being stored with a precision of seconds
+#[derive(Debug, Default)]
+pub struct InMemoryBackend {
+    store: RefCell<Store>,
+    /// Copy of the store taken by `begin`, restored by `rollback`
+    snapshot: RefCell<Option<Store>>,
+}
+
+impl InMemoryBackend {
+    /// Creates a new `InMemoryBackend` without any notes
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an `InMemoryBackend` holding notes loaded by another backend
+    pub(super) const fn from_store(store: Store) -> Self {
+        Self {
+            store: RefCell::new(store),
+            snapshot: RefCell::new(None),
+        }
+    }
+
+    /// Borrows everything stored, for backends that save it elsewhere
+    pub(super) fn store(&self) -> Ref<'_, Store> {
+        self.store.borrow()
+    }
+}
+
+/// Converts a note to a partial note, leaving out its content
+fn partial_note(note: &Note) -> PartialNote {
+    PartialNote {
+        id: note.id,
+        owner: note.owner.clone(),
+        name: note.name.clone(),
+        created_at: note.created_at,
+        updated_at: note.updated_at,
+        deleted_at: note.deleted_at,
+    }
+}
+
+impl NoteBackend for InMemoryBackend {
+    /// Stores a new note. Like in `SQLite`, a new note is never in the trash.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::Duplicate` if a note with the same ID already exists
+    fn create(&self, note: Note) -> Result<NoteId> {
+        let mut store = self.store.borrow_mut();
+        if store.notes.contains_key(&note.id) {
+            return Err(NoteError::Backend(BackendError::Duplicate));
+        }
+
+        let id = note.id;
+        let note = Note {
+            created_at: note.created_at.trunc_subsecs(0),
+            updated_at: note.updated_at.trunc_subsecs(0),
+            deleted_at: None,
+            ..note
+        };
+        trace!("Created note in memory: {note:?}");
+        store.notes.insert(id, note);
+        Ok(id)
+    }
+
+    /// Reads a copy of a note
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn read(&self, id: NoteId) -> Result<Note> {
+        self.store.borrow().note(id).cloned()
+    }
+
+    /// Reads a note without its content
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn read_partial(&self, id: NoteId) -> Result<PartialNote> {
+        self.store.borrow().note(id).map(partial_note)
+    }
+
+    /// Looks up partial notes by name, sorted by ID
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn find_by_name(
+        &self,
+        name: &str,
+        owners: &[String],
+        grantee: &str,
+    ) -> Result<Vec<PartialNote>> {
+        let store = self.store.borrow();
+        Ok(store
+            .notes
+            .values()
+            .filter(|note| {
+                note.name == name
+                    && (owners.contains(&note.owner)
+                        || store
+                            .grants
+                            .get(&note.id)
+                            .is_some_and(|grants| grants.contains_key(grantee)))
+            })
+            .map(partial_note)
+            .collect())
+    }
+
+    /// Replaces the name, owner, content and modification time of a note,
+    /// saving the replaced version as a new revision
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn update(&self, note: Note) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        let Some(stored) = store.notes.get_mut(&note.id) else {
+            return Err(NoteError::Backend(BackendError::NoteNotFound(note.id)));
+        };
+
+        let old_name = std::mem::replace(&mut stored.name, note.name);
+        let old_content = std::mem::replace(&mut stored.content, note.content);
+        let old_updated_at =
+            std::mem::replace(&mut stored.updated_at, note.updated_at.trunc_subsecs(0));
+        stored.owner = note.owner;
+
+        let revisions = store.revisions.entry(note.id).or_default();
+        let revision = revisions.last().map_or(1, |r| r.revision + 1);
+        revisions.push(Revision {
+            note_id: note.id,
+            revision,
+            name: old_name,
+            content: old_content,
+            updated_at: old_updated_at,
+        });
+        Ok(())
+    }
+
+    /// Deletes a note along with its tags, revisions, grants and references
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn delete(&self, id: NoteId) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        if store.notes.remove(&id).is_none() {
+            return Err(NoteError::Backend(BackendError::NoteNotFound(id)));
+        }
+        store.tags.remove(&id);
+        store.revisions.remove(&id);
+        store.grants.remove(&id);
+        store.links.remove(&id);
+        store.name_links.remove(&id);
+        Ok(())
+    }
+
+    /// Sets or clears the deletion time of a note. Doesn't create a revision.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn set_deleted(&self, id: NoteId, deleted_at: Option<DateTime<Utc>>) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        let Some(note) = store.notes.get_mut(&id) else {
+            return Err(NoteError::Backend(BackendError::NoteNotFound(id)));
+        };
+        note.deleted_at = deleted_at.map(|time| time.trunc_subsecs(0));
+        trace!("Set trash state of note #{id}: {deleted_at:?}");
+        Ok(())
+    }
+
+    /// Inserts or replaces the grant of a user on a note
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn set_grant(&self, id: NoteId, user: &str, permission: Permission) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        store.note(id)?;
+        store
+            .grants
+            .entry(id)
+            .or_default()
+            .insert(user.to_string(), permission);
+        trace!("Granted {permission} on note #{id} to {user}");
+        Ok(())
+    }
+
+    /// Removes the grant of a user on a note
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::GrantNotFound` if the user has no grant on the note
+    fn remove_grant(&self, id: NoteId, user: &str) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        if store
+            .grants
+            .get_mut(&id)
+            .and_then(|grants| grants.remove(user))
+            .is_none()
+        {
+            return Err(NoteError::Backend(BackendError::GrantNotFound {
+                id,
+                user: user.to_string(),
+            }));
+        }
+        trace!("Removed grant on note #{id} from {user}");
+        Ok(())
+    }
+
+    /// Returns the grants on a note, sorted by user
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list_grants(&self, id: NoteId) -> Result<Vec<Grant>> {
+        Ok(self
+            .store
+            .borrow()
+            .grants
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|(user, &permission)| Grant {
+                note_id: id,
+                user: user.clone(),
+                permission,
+            })
+            .collect())
+    }
+
+    /// Returns notes filtered by tag and trash state, sorted and paginated by the query
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list(&self, query: &ListQuery) -> Result<Vec<PartialNote>> {
+        let store = self.store.borrow();
+        let notes = store
+            .notes
+            .values()
+            .filter(|note| query.trash.matches(note.deleted_at))
+            .filter(|note| {
+                query
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| store.tags.get(&note.id).is_some_and(|t| t.contains(tag)))
+            })
+            .map(partial_note)
+            .collect();
+        Ok(query.sort_and_paginate(notes))
+    }
+
+    /// Returns the IDs of all notes, sorted
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list_ids(&self) -> Result<Vec<NoteId>> {
+        Ok(self.store.borrow().notes.keys().copied().collect())
+    }
+
+    /// Moves a note aside and deletes it along with everything attached to it.
+    /// Notes in memory can't be corrupted, so this is only here for completeness
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn quarantine(&self, id: NoteId) -> Result<()> {
+        let note = self.read(id)?;
+        self.delete(id)?;
+        self.store.borrow_mut().quarantined.push(note);
+        trace!("Quarantined note #{id}");
+        Ok(())
+    }
+
+    /// Replaces the tags of a note. Duplicates are ignored
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn set_tags(&self, id: NoteId, tags: &[String]) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        store.note(id)?;
+        store.tags.insert(id, tags.iter().cloned().collect());
+        trace!("Set tags of note #{id}: {tags:?}");
+        Ok(())
+    }
+
+    /// Returns the tags of a note, sorted alphabetically
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn read_tags(&self, id: NoteId) -> Result<Vec<String>> {
+        Ok(self
+            .store
+            .borrow()
+            .tags
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect())
+    }
+
+    /// Replaces the notes and names a note references. Duplicates are ignored
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoteNotFound` if no note with the given ID exists
+    fn set_links(&self, id: NoteId, targets: &[NoteId], names: &[String]) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        store.note(id)?;
+        store.links.insert(id, targets.iter().copied().collect());
+        store.name_links.insert(id, names.iter().cloned().collect());
+        trace!("Set references of note #{id}: {targets:?}, by name: {names:?}");
+        Ok(())
+    }
+
+    /// Returns the notes referencing a note, sorted by ID
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn backlinks(&self, id: NoteId) -> Result<Vec<NoteId>> {
+        Ok(self
+            .store
+            .borrow()
+            .links
+            .iter()
+            .filter(|(_, targets)| targets.contains(&id))
+            .map(|(&source, _)| source)
+            .collect())
+    }
+
+    /// Returns the notes referencing any of the names, sorted by ID
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn name_backlinks(&self, names: &[&str]) -> Result<Vec<NoteId>> {
+        Ok(self
+            .store
+            .borrow()
+            .name_links
+            .iter()
+            .filter(|(_, referenced)| names.iter().any(|&name| referenced.contains(name)))
+            .map(|(&source, _)| source)
+            .collect())
+    }
+
+    /// References are indexed as notes are written, so the index is always complete
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn links_indexed(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Does nothing, as the reference index is always complete
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn mark_links_indexed(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Searches note names and contents for notes where every term starts a word, ignoring case,
+    /// like the prefix queries of the `SQLite` search index. Results are sorted by ID instead of relevance
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let terms = search_terms(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let store = self.store.borrow();
+        let results = store
+            .notes
+            .values()
+            .filter_map(|note| {
+                // Like `SQLite`, take the snippet from the content, even if only the name matches
+                let mut search = LineSearch::new(&terms);
+                note.name.lines().for_each(|line| search.name_line(line));
+                note.content
+                    .lines()
+                    .for_each(|line| search.content_line(line));
+                search.finish().map(|snippet| SearchResult {
+                    note: partial_note(note),
+                    snippet,
+                })
+            })
+            .collect();
+        Ok(results)
+    }
+
+    /// Returns all revisions of a note, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Never fails
+    fn list_revisions(&self, id: NoteId) -> Result<Vec<Revision>> {
+        Ok(self
+            .store
+            .borrow()
+            .revisions
+            .get(&id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    /// Reads a single revision of a note
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::RevisionNotFound` if the note has no such revision
+    fn read_revision(&self, id: NoteId, revision: u32) -> Result<Revision> {
+        self.store
+            .borrow()
+            .revisions
+            .get(&id)
+            .and_then(|revisions| revisions.iter().find(|r| r.revision == revision))
+            .cloned()
+            .ok_or(NoteError::Backend(BackendError::RevisionNotFound {
+                id,
+                revision,
+            }))
+    }
+
+    /// Starts a transaction by taking a snapshot of all notes
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::TransactionAlreadyActive` if a transaction is already active
+    fn begin(&self) -> Result<()> {
+        let mut snapshot = self.snapshot.borrow_mut();
+        if snapshot.is_some() {
+            return Err(NoteError::Backend(BackendError::TransactionAlreadyActive));
+        }
+        *snapshot = Some(self.store.borrow().clone());
+        trace!("Began transaction");
+        Ok(())
+    }
+
+    /// Commits the active transaction by dropping its snapshot
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoActiveTransaction` if no transaction is active
+    fn commit(&self) -> Result<()> {
+        if self.snapshot.borrow_mut().take().is_none() {
+            return Err(NoteError::Backend(BackendError::NoActiveTransaction));
+        }
+        trace!("Committed transaction");
+        Ok(())
+    }
+
+    /// Rolls back the active transaction by restoring its snapshot
+    ///
+    /// # Errors
+    ///
+    /// Returns `BackendError::NoActiveTransaction` if no transaction is active
+    fn rollback(&self) -> Result<()> {
+        let Some(snapshot) = self.snapshot.borrow_mut().take() else {
+            return Err(NoteError::Backend(BackendError::NoActiveTransaction));
+        };
+        *self.store.borrow_mut() = snapshot;
+        trace!("Rolled back transaction");
+        Ok(())
+    }
+}
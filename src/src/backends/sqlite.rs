@@ -1,6 +1,13 @@
-use super::{BackendError, Note, NoteBackend, NoteError, PartialNote, Result};
+use super::{
+    BackendError, Grant, ListQuery, Note, NoteBackend, NoteError, NoteId, PartialNote, Permission,
+    Result, Revision, SearchResult, SortDirection, SortKey, TrashFilter,
+};
+use chrono::{DateTime, Utc};
 use log::{debug, trace};
-use rusqlite::{params, Connection, Error as SqliteError, ErrorCode, OptionalExtension};
+use rusqlite::{
+    params, params_from_iter, Connection, Error as SqliteError, ErrorCode, OptionalExtension, Row,
+};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct SqliteBackend {
@@ -9,34 +16,308 @@ pub struct SqliteBackend {
 
 impl SqliteBackend {
     /// Creates a new `SqliteBackend` by opening the `SQLite` database at the given path.
-    /// Also ensures that the `notes` table exists.
+    /// Also ensures that the `notes`, `note_tags`, `note_revisions`, `note_grants`, `note_links` and `note_name_links`
+    /// tables and the `notes_fts` search index exist and are upgraded to the current schema.
     ///
     /// # Errors
     ///
     /// `BackendError::DatabaseCreationError` if the database file cannot be opened
-    /// `BackendError::TableCreationError` if the `notes` table cannot be created.
+    /// `BackendError::TableCreationError` if the tables cannot be created.
+    /// `BackendError::SchemaMigrationError` if an existing `notes` table cannot be upgraded.
     pub fn new(path: &str) -> Result<Self> {
         let connection = Connection::open(path)
             .map_err(|_| NoteError::Backend(BackendError::DatabaseCreationError))?;
         debug!("Opened connection to db: {}", &path);
 
-        // Create notes table if it doesn't exist
+        // Wait for other writers to finish their transactions instead of failing right away
+        connection
+            .busy_timeout(Duration::from_secs(5))
+            .map_err(|_| NoteError::Backend(BackendError::DatabaseCreationError))?;
+
+        // Create notes table if it doesn't exist. `INTEGER` columns are 64-bit,
+        // so tables created back when note IDs were 16-bit need no upgrade
         connection
             .execute(
                 "
                 CREATE TABLE IF NOT EXISTS notes (
-                    id      INTEGER PRIMARY KEY,
-                    name    TEXT NOT NULL,
-                    owner   TEXT NOT NULL,
-                    content TEXT NOT NULL
+                    id         INTEGER PRIMARY KEY,
+                    name       TEXT NOT NULL,
+                    owner      TEXT NOT NULL,
+                    content    TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    deleted_at INTEGER
                 )
                 ",
                 [],
             )
             .map_err(|_e| NoteError::Backend(BackendError::TableCreationError))?;
+        connection
+            .execute(
+                "CREATE INDEX IF NOT EXISTS notes_owner_name ON notes (owner, name)",
+                [],
+            )
+            .map_err(|_e| NoteError::Backend(BackendError::TableCreationError))?;
         debug!("Initialized db with `notes` table");
+
+        // Tags are removed together with their note
+        connection
+            .execute_batch(
+                "
+                PRAGMA foreign_keys = ON;
+                CREATE TABLE IF NOT EXISTS note_tags (
+                    note_id INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+                    tag     TEXT NOT NULL,
+                    PRIMARY KEY (note_id, tag)
+                );
+                CREATE INDEX IF NOT EXISTS note_tags_tag ON note_tags (tag);
+                ",
+            )
+            .map_err(|_e| NoteError::Backend(BackendError::TableCreationError))?;
+        debug!("Initialized db with `note_tags` table");
+
+        // Revisions are removed together with their note
+        connection
+            .execute(
+                "
+                CREATE TABLE IF NOT EXISTS note_revisions (
+                    note_id    INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+                    revision   INTEGER NOT NULL,
+                    name       TEXT NOT NULL,
+                    content    TEXT NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    PRIMARY KEY (note_id, revision)
+                )
+                ",
+                [],
+            )
+            .map_err(|_e| NoteError::Backend(BackendError::TableCreationError))?;
+        debug!("Initialized db with `note_revisions` table");
+
+        // Grants are removed together with their note
+        connection
+            .execute(
+                "
+                CREATE TABLE IF NOT EXISTS note_grants (
+                    note_id    INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+                    user       TEXT NOT NULL,
+                    permission TEXT NOT NULL,
+                    PRIMARY KEY (note_id, user)
+                )
+                ",
+                [],
+            )
+            .map_err(|_e| NoteError::Backend(BackendError::TableCreationError))?;
+        debug!("Initialized db with `note_grants` table");
+
+        // References from a note are removed together with it. The referenced note may be gone,
+        // and names are kept whether or not they resolve, so they can be resolved again when notes change
+        connection
+            .execute_batch(
+                "
+                CREATE TABLE IF NOT EXISTS note_links (
+                    source_id INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+                    target_id INTEGER NOT NULL,
+                    PRIMARY KEY (source_id, target_id)
+                );
+                CREATE INDEX IF NOT EXISTS note_links_target ON note_links (target_id);
+                CREATE TABLE IF NOT EXISTS note_name_links (
+                    source_id INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+                    name      TEXT NOT NULL,
+                    PRIMARY KEY (source_id, name)
+                );
+                CREATE INDEX IF NOT EXISTS note_name_links_name ON note_name_links (name);
+                ",
+            )
+            .map_err(|_e| NoteError::Backend(BackendError::TableCreationError))?;
+        debug!("Initialized db with `note_links` and `note_name_links` tables");
+
+        Self::migrate(&connection)
+            .map_err(|_e| NoteError::Backend(BackendError::SchemaMigrationError))?;
+
+        Self::create_search_index(&connection)
+            .map_err(|_e| NoteError::Backend(BackendError::TableCreationError))?;
+        debug!("Initialized db with `notes_fts` search index");
         Ok(Self { connection })
     }
+
+    /// Creates the `notes_fts` full-text index over note names and contents, along with the
+    /// triggers keeping it in sync with `notes`. Notes that existed before the index are indexed once.
+    fn create_search_index(connection: &Connection) -> rusqlite::Result<()> {
+        let exists: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'notes_fts'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        connection.execute_batch(
+            "
+            CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5 (
+                name, content, content = 'notes', content_rowid = 'id'
+            );
+            CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes BEGIN
+                INSERT INTO notes_fts (rowid, name, content) VALUES (new.id, new.name, new.content);
+            END;
+            CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, name, content)
+                    VALUES ('delete', old.id, old.name, old.content);
+            END;
+            CREATE TRIGGER IF NOT EXISTS notes_fts_update AFTER UPDATE ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, name, content)
+                    VALUES ('delete', old.id, old.name, old.content);
+                INSERT INTO notes_fts (rowid, name, content) VALUES (new.id, new.name, new.content);
+            END;
+            ",
+        )?;
+
+        if !exists {
+            connection.execute("INSERT INTO notes_fts (notes_fts) VALUES ('rebuild')", [])?;
+        }
+        Ok(())
+    }
+
+    /// Adds columns missing from `notes` tables created by older versions.
+    ///
+    /// Notes created before timestamps existed get the time of the upgrade,
+    /// since their real creation and modification times are unknown.
+    /// Notes from before the trash existed are not trashed.
+    fn migrate(connection: &Connection) -> rusqlite::Result<()> {
+        for (column, definition, backfill) in [
+            (
+                "created_at",
+                "INTEGER NOT NULL DEFAULT 0",
+                Some("CAST(strftime('%s', 'now') AS INTEGER)"),
+            ),
+            (
+                "updated_at",
+                "INTEGER NOT NULL DEFAULT 0",
+                Some("CAST(strftime('%s', 'now') AS INTEGER)"),
+            ),
+            ("deleted_at", "INTEGER", None),
+        ] {
+            let exists: bool = connection.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('notes') WHERE name = ?1",
+                [column],
+                |row| row.get(0),
+            )?;
+            if exists {
+                continue;
+            }
+
+            connection.execute(
+                &format!("ALTER TABLE notes ADD COLUMN {column} {definition}"),
+                [],
+            )?;
+            if let Some(value) = backfill {
+                connection.execute(&format!("UPDATE notes SET {column} = {value}"), [])?;
+            }
+            debug!("Upgraded `notes` table with `{column}` column");
+        }
+        Ok(())
+    }
+
+    /// Runs an operation inside a savepoint, so it either applies completely or not at all.
+    /// Savepoints nest, so this also works inside a transaction started with `begin`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the operation, or a mapped `SQLite` error if the savepoint fails.
+    fn savepoint<T>(&self, operation: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.connection
+            .execute_batch("SAVEPOINT operation")
+            .map_err(map_sqlite_error)?;
+
+        match operation() {
+            Ok(value) => {
+                self.connection
+                    .execute_batch("RELEASE operation")
+                    .map_err(map_sqlite_error)?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.connection
+                    .execute_batch("ROLLBACK TO operation; RELEASE operation")
+                    .map_err(map_sqlite_error)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Deletes all tags of a note and inserts the new ones
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists.
+    /// - Other mapped `SQLite` errors if the tags cannot be stored.
+    fn replace_tags(&self, id: NoteId, tags: &[String]) -> Result<()> {
+        let exists: bool = self
+            .connection
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM notes WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .map_err(map_sqlite_error)?;
+        if !exists {
+            return Err(NoteError::Backend(BackendError::NoteNotFound(id)));
+        }
+
+        self.connection
+            .execute("DELETE FROM note_tags WHERE note_id = ?1", [id])
+            .map_err(map_sqlite_error)?;
+        for tag in tags {
+            self.connection
+                .execute(
+                    "INSERT OR IGNORE INTO note_tags (note_id, tag) VALUES (?1, ?2)",
+                    params![id, tag],
+                )
+                .map_err(map_sqlite_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// `user_version` of databases where `note_links` and `note_name_links` cover every note.
+/// Version 1 only covered `note_links`
+const LINKS_INDEXED_VERSION: i64 = 2;
+
+/// Converts a Unix timestamp stored in the database to a `DateTime<Utc>`
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
+
+/// Turns free text into an FTS5 query where every term must match as a prefix,
+/// quoting terms so characters like `"`, `*` or `-` aren't parsed as query syntax
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Maps a row of `id, name, owner, created_at, updated_at, deleted_at` to a `PartialNote`
+fn partial_note_from_row(row: &Row) -> rusqlite::Result<PartialNote> {
+    Ok(PartialNote {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        owner: row.get(2)?,
+        created_at: timestamp(row.get(3)?),
+        updated_at: timestamp(row.get(4)?),
+        deleted_at: row.get::<_, Option<i64>>(5)?.map(timestamp),
+    })
+}
+
+/// Maps a row of `note_id, revision, name, content, updated_at` to a `Revision`
+fn revision_from_row(row: &Row) -> rusqlite::Result<Revision> {
+    Ok(Revision {
+        note_id: row.get(0)?,
+        revision: row.get(1)?,
+        name: row.get(2)?,
+        content: row.get(3)?,
+        updated_at: timestamp(row.get(4)?),
+    })
 }
 
 /// Maps a `rusqlite::Error` into a `NoteError`, wrapping known SQLite-specific codes into domain-specific variants.
@@ -70,15 +351,31 @@ impl NoteBackend for SqliteBackend {
     /// # Errors
     ///
     /// Returns:
+    /// - `BackendError::Duplicate` if a note with the same ID already exists.
     /// - `BackendError::Timeout`, `PermissionDenied`, `NotADatabase`, or other mapped SQLite-specific errors.
     /// - `BackendError::Other` if an unknown `SQLite` error occurs.
-    fn create(&self, note: Note) -> Result<u16> {
+    fn create(&self, note: Note) -> Result<NoteId> {
         self.connection
             .execute(
-                "INSERT INTO notes (id, name, owner, content) VALUES (?1, ?2, ?3, ?4)",
-                params![note.id, note.name, note.owner, note.content],
+                "INSERT INTO notes (id, name, owner, content, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    note.id,
+                    note.name,
+                    note.owner,
+                    note.content,
+                    note.created_at.timestamp(),
+                    note.updated_at.timestamp()
+                ],
             )
-            .map_err(map_sqlite_error)?;
+            .map_err(|e| match e {
+                SqliteError::SqliteFailure(code, _)
+                    if code.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY =>
+                {
+                    NoteError::Backend(BackendError::Duplicate)
+                }
+                other => map_sqlite_error(other),
+            })?;
         trace!("Created row with note data: {note:?}");
         Ok(note.id)
     }
@@ -90,10 +387,11 @@ impl NoteBackend for SqliteBackend {
     /// Returns:
     /// - `BackendError::NoteNotFound` if no note with the given ID exists.
     /// - Other mapped `SQLite` errors for query failure.
-    fn read(&self, id: u16) -> Result<Note> {
+    fn read(&self, id: NoteId) -> Result<Note> {
         self.connection
             .query_row(
-                "SELECT id, name, owner, content FROM notes WHERE id = ?1",
+                "SELECT id, name, owner, content, created_at, updated_at, deleted_at
+                 FROM notes WHERE id = ?1",
                 params![id],
                 |row| {
                     Ok(Note {
@@ -101,6 +399,9 @@ impl NoteBackend for SqliteBackend {
                         name: row.get(1)?,
                         owner: row.get(2)?,
                         content: row.get(3)?,
+                        created_at: timestamp(row.get(4)?),
+                        updated_at: timestamp(row.get(5)?),
+                        deleted_at: row.get::<_, Option<i64>>(6)?.map(timestamp),
                     })
                 },
             )
@@ -109,32 +410,71 @@ impl NoteBackend for SqliteBackend {
             .ok_or(NoteError::Backend(BackendError::NoteNotFound(id)))
     }
 
-    /// Reads a note by ID, returning only its ID, name, and owner (no content).
+    /// Reads a note by ID, returning only its ID, name, owner and timestamps (no content).
     ///
     /// # Errors
     ///
     /// Returns:
     /// - `BackendError::NoteNotFound` if no note with the given ID exists.
     /// - Other mapped `SQLite` errors for query failure.
-    fn read_partial(&self, id: u16) -> Result<PartialNote> {
+    fn read_partial(&self, id: NoteId) -> Result<PartialNote> {
         self.connection
             .query_row(
-                "SELECT id, name, owner FROM notes WHERE id = ?1",
+                "SELECT id, name, owner, created_at, updated_at, deleted_at FROM notes WHERE id = ?1",
                 params![id],
-                |row| {
-                    Ok(PartialNote {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        owner: row.get(2)?,
-                    })
-                },
+                partial_note_from_row,
             )
             .optional()
             .map_err(map_sqlite_error)?
             .ok_or(NoteError::Backend(BackendError::NoteNotFound(id)))
     }
 
-    /// Updates an existing note's name, owner, and content.
+    /// Looks up partial notes by name through the `notes_owner_name` index for each owner,
+    /// and through `note_grants` for notes shared with the grantee.
+    ///
+    /// # Errors
+    ///
+    /// Returns mapped `SQLite` errors if the query fails.
+    fn find_by_name(
+        &self,
+        name: &str,
+        owners: &[String],
+        grantee: &str,
+    ) -> Result<Vec<PartialNote>> {
+        let placeholders = (0..owners.len())
+            .map(|i| format!("?{}", i + 3))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut stmt = self
+            .connection
+            .prepare(&format!(
+                "SELECT id, name, owner, created_at, updated_at, deleted_at FROM notes
+                 WHERE name = ?1 AND (
+                     owner IN ({placeholders})
+                     OR id IN (SELECT note_id FROM note_grants WHERE user = ?2)
+                 )
+                 ORDER BY id ASC"
+            ))
+            .map_err(map_sqlite_error)?;
+
+        let notes_iter = stmt
+            .query_map(
+                params_from_iter(
+                    [name, grantee]
+                        .into_iter()
+                        .chain(owners.iter().map(String::as_str)),
+                ),
+                partial_note_from_row,
+            )
+            .map_err(map_sqlite_error)?;
+
+        notes_iter
+            .collect::<std::result::Result<_, _>>()
+            .map_err(map_sqlite_error)
+    }
+
+    /// Updates an existing note's name, owner, content and modification time,
+    /// copying the replaced version to `note_revisions` in the same savepoint.
     ///
     /// # Errors
     ///
@@ -142,64 +482,532 @@ impl NoteBackend for SqliteBackend {
     /// - `BackendError::NoteNotFound` if no note with the given ID exists.
     /// - Other backend errors if the update fails due to `SQLite` issues.
     fn update(&self, note: Note) -> Result<()> {
+        self.savepoint(|| {
+            self.connection
+                .execute(
+                    "INSERT INTO note_revisions (note_id, revision, name, content, updated_at)
+                     SELECT id,
+                            (SELECT COALESCE(MAX(revision), 0) + 1 FROM note_revisions WHERE note_id = ?1),
+                            name, content, updated_at
+                     FROM notes WHERE id = ?1",
+                    [note.id],
+                )
+                .map_err(map_sqlite_error)?;
+
+            let rows = self
+                .connection
+                .execute(
+                    "UPDATE notes SET name = ?1, owner = ?2, content = ?3, updated_at = ?4 WHERE id = ?5",
+                    params![
+                        note.name,
+                        note.owner,
+                        note.content,
+                        note.updated_at.timestamp(),
+                        note.id
+                    ],
+                )
+                .map_err(map_sqlite_error)?;
+
+            if rows == 0 {
+                Err(NoteError::Backend(BackendError::NoteNotFound(note.id)))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Deletes a note by ID from the database. Its tags, revisions, grants and references are removed by `ON DELETE CASCADE`.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note was not found.
+    /// - Other backend errors if the deletion operation fails.
+    fn delete(&self, id: NoteId) -> Result<()> {
         let rows = self
             .connection
-            .execute(
-                "UPDATE notes SET name = ?1, owner = ?2, content = ?3 WHERE id = ?4",
-                params![note.name, note.owner, note.content, note.id],
-            )
+            .execute("DELETE FROM notes WHERE id = ?1", [id])
             .map_err(map_sqlite_error)?;
 
         if rows == 0 {
-            Err(NoteError::Backend(BackendError::NoteNotFound(note.id)))
+            Err(NoteError::Backend(BackendError::NoteNotFound(id)))
         } else {
             Ok(())
         }
     }
-    /// Deletes a note by ID from the database.
+
+    /// Sets or clears the `deleted_at` column of a note. Doesn't create a revision.
     ///
     /// # Errors
     ///
     /// Returns:
     /// - `BackendError::NoteNotFound` if the note was not found.
-    /// - Other backend errors if the deletion operation fails.
-    fn delete(&self, id: u16) -> Result<()> {
+    /// - Other backend errors if the update fails due to `SQLite` issues.
+    fn set_deleted(&self, id: NoteId, deleted_at: Option<DateTime<Utc>>) -> Result<()> {
         let rows = self
             .connection
-            .execute("DELETE FROM notes WHERE id = ?1", [id])
+            .execute(
+                "UPDATE notes SET deleted_at = ?1 WHERE id = ?2",
+                params![deleted_at.map(|time| time.timestamp()), id],
+            )
             .map_err(map_sqlite_error)?;
 
         if rows == 0 {
             Err(NoteError::Backend(BackendError::NoteNotFound(id)))
         } else {
+            trace!("Set trash state of note #{id}: {deleted_at:?}");
+            Ok(())
+        }
+    }
+
+    /// Inserts or replaces the grant of a user on a note.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists.
+    /// - Other mapped `SQLite` errors if the grant cannot be stored.
+    fn set_grant(&self, id: NoteId, user: &str, permission: Permission) -> Result<()> {
+        self.savepoint(|| {
+            self.read_partial(id)?;
+            self.connection
+                .execute(
+                    "INSERT OR REPLACE INTO note_grants (note_id, user, permission) VALUES (?1, ?2, ?3)",
+                    params![id, user, permission.as_str()],
+                )
+                .map_err(map_sqlite_error)
+        })?;
+        trace!("Granted {permission} on note #{id} to {user}");
+        Ok(())
+    }
+
+    /// Deletes the grant of a user on a note.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::GrantNotFound` if the user has no grant on the note.
+    /// - Other mapped `SQLite` errors if the grant cannot be removed.
+    fn remove_grant(&self, id: NoteId, user: &str) -> Result<()> {
+        let rows = self
+            .connection
+            .execute(
+                "DELETE FROM note_grants WHERE note_id = ?1 AND user = ?2",
+                params![id, user],
+            )
+            .map_err(map_sqlite_error)?;
+
+        if rows == 0 {
+            Err(NoteError::Backend(BackendError::GrantNotFound {
+                id,
+                user: user.to_string(),
+            }))
+        } else {
+            trace!("Removed grant on note #{id} from {user}");
             Ok(())
         }
     }
 
-    /// Returns a list of all notes in the database, sorted by ID. The notes include only metadata: ID, name, and owner.
+    /// Returns the grants on a note, sorted by user. Grants with an unknown permission are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - A backend error if the query fails or the data cannot be retrieved.
+    fn list_grants(&self, id: NoteId) -> Result<Vec<Grant>> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT user, permission FROM note_grants WHERE note_id = ?1 ORDER BY user ASC",
+            )
+            .map_err(map_sqlite_error)?;
+
+        let grants_iter = stmt
+            .query_map([id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(map_sqlite_error)?;
+
+        let mut grants = Vec::new();
+        for grant in grants_iter {
+            let (user, permission) = grant.map_err(map_sqlite_error)?;
+            if let Some(permission) = Permission::parse(&permission) {
+                grants.push(Grant {
+                    note_id: id,
+                    user,
+                    permission,
+                });
+            }
+        }
+        Ok(grants)
+    }
+
+    /// Returns a list of notes in the database, filtered by tag and trash state, sorted and paginated in SQL.
+    /// The notes include only metadata: ID, name, owner and timestamps.
     ///
     /// # Errors
     ///
     /// Returns:
     /// - A backend error if the query fails or the data cannot be retrieved.
-    fn list(&self) -> Result<Vec<PartialNote>> {
+    fn list(&self, query: &ListQuery) -> Result<Vec<PartialNote>> {
+        let column = match query.sort {
+            SortKey::Id => "id",
+            SortKey::Name => "name",
+            SortKey::Owner => "owner",
+            SortKey::CreatedAt => "created_at",
+            SortKey::UpdatedAt => "updated_at",
+        };
+        let direction = match query.direction {
+            SortDirection::Ascending => "ASC",
+            SortDirection::Descending => "DESC",
+        };
+        let trash = match query.trash {
+            TrashFilter::Exclude => "deleted_at IS NULL",
+            TrashFilter::Only => "deleted_at IS NOT NULL",
+            TrashFilter::Include => "TRUE",
+        };
+        // A negative limit means no limit in SQLite
+        let limit = query
+            .limit
+            .map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let offset = i64::try_from(query.offset).unwrap_or(i64::MAX);
+
         let mut stmt = self
             .connection
-            .prepare("SELECT id, name, owner FROM notes ORDER BY id ASC")
+            .prepare(&format!(
+                "SELECT id, name, owner, created_at, updated_at, deleted_at FROM notes n
+                 WHERE {trash} AND (?1 IS NULL OR EXISTS (
+                     SELECT 1 FROM note_tags t WHERE t.note_id = n.id AND t.tag = ?1
+                 ))
+                 ORDER BY {column} {direction}, id ASC
+                 LIMIT ?2 OFFSET ?3"
+            ))
             .map_err(map_sqlite_error)?;
 
         let notes_iter = stmt
-            .query_map([], |row| {
-                Ok(PartialNote {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    owner: row.get(2)?,
+            .query_map(params![query.tag, limit, offset], partial_note_from_row)
+            .map_err(map_sqlite_error)?;
+
+        notes_iter
+            .collect::<std::result::Result<_, _>>()
+            .map_err(map_sqlite_error)
+    }
+
+    /// Returns the IDs of all rows in the `notes` table, whether or not their columns can be read.
+    ///
+    /// # Errors
+    ///
+    /// Returns mapped `SQLite` errors if the query fails.
+    fn list_ids(&self) -> Result<Vec<NoteId>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT id FROM notes ORDER BY id ASC")
+            .map_err(map_sqlite_error)?;
+
+        let ids_iter = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(map_sqlite_error)?;
+
+        ids_iter
+            .collect::<std::result::Result<_, _>>()
+            .map_err(map_sqlite_error)
+    }
+
+    /// Copies the row of a note as is to the untyped `quarantined_notes` table and deletes it, in a savepoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if the note was not found.
+    /// - Other mapped `SQLite` errors if the row cannot be copied or deleted.
+    fn quarantine(&self, id: NoteId) -> Result<()> {
+        self.savepoint(|| {
+            self.connection
+                .execute_batch(
+                    "CREATE TABLE IF NOT EXISTS quarantined_notes (
+                        id, name, owner, content, created_at, updated_at, deleted_at,
+                        quarantined_at INTEGER NOT NULL
+                    )",
+                )
+                .map_err(map_sqlite_error)?;
+            self.connection
+                .execute(
+                    "INSERT INTO quarantined_notes
+                     SELECT id, name, owner, content, created_at, updated_at, deleted_at, ?2
+                     FROM notes WHERE id = ?1",
+                    params![id, Utc::now().timestamp()],
+                )
+                .map_err(map_sqlite_error)?;
+            self.delete(id)
+        })?;
+        trace!("Quarantined note #{id}");
+        Ok(())
+    }
+
+    /// Replaces the tags of a note inside a savepoint, so a failure never leaves a partial tag set.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists.
+    /// - Other mapped `SQLite` errors if the tags cannot be stored.
+    fn set_tags(&self, id: NoteId, tags: &[String]) -> Result<()> {
+        self.savepoint(|| self.replace_tags(id, tags))?;
+        trace!("Set tags of note #{id}: {tags:?}");
+        Ok(())
+    }
+
+    /// Returns the tags of a note, sorted alphabetically.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - A backend error if the query fails or the data cannot be retrieved.
+    fn read_tags(&self, id: NoteId) -> Result<Vec<String>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT tag FROM note_tags WHERE note_id = ?1 ORDER BY tag ASC")
+            .map_err(map_sqlite_error)?;
+
+        let tags_iter = stmt
+            .query_map([id], |row| row.get(0))
+            .map_err(map_sqlite_error)?;
+
+        tags_iter
+            .collect::<std::result::Result<_, _>>()
+            .map_err(map_sqlite_error)
+    }
+
+    /// Replaces the rows of a note in `note_links` and `note_name_links` inside a savepoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::NoteNotFound` if no note with the given ID exists.
+    /// - Other mapped `SQLite` errors if the references cannot be stored.
+    fn set_links(&self, id: NoteId, targets: &[NoteId], names: &[String]) -> Result<()> {
+        self.savepoint(|| {
+            self.read_partial(id)?;
+            self.connection
+                .execute("DELETE FROM note_links WHERE source_id = ?1", [id])
+                .map_err(map_sqlite_error)?;
+            self.connection
+                .execute("DELETE FROM note_name_links WHERE source_id = ?1", [id])
+                .map_err(map_sqlite_error)?;
+            for target in targets {
+                self.connection
+                    .execute(
+                        "INSERT OR IGNORE INTO note_links (source_id, target_id) VALUES (?1, ?2)",
+                        params![id, target],
+                    )
+                    .map_err(map_sqlite_error)?;
+            }
+            for name in names {
+                self.connection
+                    .execute(
+                        "INSERT OR IGNORE INTO note_name_links (source_id, name) VALUES (?1, ?2)",
+                        params![id, name],
+                    )
+                    .map_err(map_sqlite_error)?;
+            }
+            Ok(())
+        })?;
+        trace!("Set references of note #{id}: {targets:?}, by name: {names:?}");
+        Ok(())
+    }
+
+    /// Looks up the notes referencing a note through the `note_links_target` index.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - A backend error if the query fails or the data cannot be retrieved.
+    fn backlinks(&self, id: NoteId) -> Result<Vec<NoteId>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT source_id FROM note_links WHERE target_id = ?1 ORDER BY source_id ASC")
+            .map_err(map_sqlite_error)?;
+
+        let backlinks_iter = stmt
+            .query_map([id], |row| row.get(0))
+            .map_err(map_sqlite_error)?;
+
+        backlinks_iter
+            .collect::<std::result::Result<_, _>>()
+            .map_err(map_sqlite_error)
+    }
+
+    /// Looks up the notes referencing any of the names through the `note_name_links_name` index.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - A backend error if the query fails or the data cannot be retrieved.
+    fn name_backlinks(&self, names: &[&str]) -> Result<Vec<NoteId>> {
+        let placeholders = (1..=names.len())
+            .map(|i| format!("?{i}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut stmt = self
+            .connection
+            .prepare(&format!(
+                "SELECT DISTINCT source_id FROM note_name_links WHERE name IN ({placeholders})
+                 ORDER BY source_id ASC"
+            ))
+            .map_err(map_sqlite_error)?;
+
+        let backlinks_iter = stmt
+            .query_map(params_from_iter(names), |row| row.get(0))
+            .map_err(map_sqlite_error)?;
+
+        backlinks_iter
+            .collect::<std::result::Result<_, _>>()
+            .map_err(map_sqlite_error)
+    }
+
+    /// Checks if `user_version` says `note_links` and `note_name_links` cover every note.
+    ///
+    /// # Errors
+    ///
+    /// Returns mapped `SQLite` errors if the query fails.
+    fn links_indexed(&self) -> Result<bool> {
+        let version: i64 = self
+            .connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(map_sqlite_error)?;
+        Ok(version >= LINKS_INDEXED_VERSION)
+    }
+
+    /// Sets `user_version` to mark `note_links` and `note_name_links` as covering every note.
+    ///
+    /// # Errors
+    ///
+    /// Returns mapped `SQLite` errors if the version cannot be stored.
+    fn mark_links_indexed(&self) -> Result<()> {
+        self.connection
+            .execute_batch(&format!("PRAGMA user_version = {LINKS_INDEXED_VERSION}"))
+            .map_err(map_sqlite_error)
+    }
+
+    /// Searches note names and contents through the `notes_fts` index, best matches first.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - A backend error if the query fails or the data cannot be retrieved.
+    fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT n.id, n.name, n.owner, n.created_at, n.updated_at, n.deleted_at,
+                        snippet(notes_fts, 1, '[', ']', '...', 8)
+                 FROM notes_fts JOIN notes n ON n.id = notes_fts.rowid
+                 WHERE notes_fts MATCH ?1
+                 ORDER BY rank",
+            )
+            .map_err(map_sqlite_error)?;
+
+        let results_iter = stmt
+            .query_map([fts_query(query)], |row| {
+                Ok(SearchResult {
+                    note: partial_note_from_row(row)?,
+                    // Keep snippets on one line, like the filesystem backend does
+                    snippet: row.get::<_, String>(6)?.replace('\n', " "),
                 })
             })
             .map_err(map_sqlite_error)?;
 
-        notes_iter
+        results_iter
+            .collect::<std::result::Result<_, _>>()
+            .map_err(map_sqlite_error)
+    }
+
+    /// Returns all revisions of a note, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - A backend error if the query fails or the data cannot be retrieved.
+    fn list_revisions(&self, id: NoteId) -> Result<Vec<Revision>> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT note_id, revision, name, content, updated_at FROM note_revisions
+                 WHERE note_id = ?1 ORDER BY revision ASC",
+            )
+            .map_err(map_sqlite_error)?;
+
+        let revisions_iter = stmt
+            .query_map([id], revision_from_row)
+            .map_err(map_sqlite_error)?;
+
+        revisions_iter
             .collect::<std::result::Result<_, _>>()
             .map_err(map_sqlite_error)
     }
+
+    /// Reads a single revision of a note.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::RevisionNotFound` if the note has no such revision.
+    /// - Other mapped `SQLite` errors for query failure.
+    fn read_revision(&self, id: NoteId, revision: u32) -> Result<Revision> {
+        self.connection
+            .query_row(
+                "SELECT note_id, revision, name, content, updated_at FROM note_revisions
+                 WHERE note_id = ?1 AND revision = ?2",
+                params![id, revision],
+                revision_from_row,
+            )
+            .optional()
+            .map_err(map_sqlite_error)?
+            .ok_or(NoteError::Backend(BackendError::RevisionNotFound {
+                id,
+                revision,
+            }))
+    }
+
+    /// Starts a transaction with `BEGIN IMMEDIATE`, taking the database write lock right away
+    /// so reads made inside the transaction can't be invalidated by other writers.
+    ///
+    /// # Errors
+    ///
+    /// Returns:
+    /// - `BackendError::Timeout` if another writer holds the lock for too long.
+    /// - Other mapped `SQLite` errors, e.g. if a transaction is already active.
+    fn begin(&self) -> Result<()> {
+        self.connection
+            .execute_batch("BEGIN IMMEDIATE")
+            .map_err(map_sqlite_error)?;
+        trace!("Began transaction");
+        Ok(())
+    }
+
+    /// Commits the active transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns mapped `SQLite` errors, e.g. if no transaction is active.
+    fn commit(&self) -> Result<()> {
+        self.connection
+            .execute_batch("COMMIT")
+            .map_err(map_sqlite_error)?;
+        trace!("Committed transaction");
+        Ok(())
+    }
+
+    /// Rolls back the active transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns mapped `SQLite` errors, e.g. if no transaction is active.
+    fn rollback(&self) -> Result<()> {
+        self.connection
+            .execute_batch("ROLLBACK")
+            .map_err(map_sqlite_error)?;
+        trace!("Rolled back transaction");
+        Ok(())
+    }
 }
@@ -0,0 +1,85 @@
+use crate::NoteId;
+
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Notes and the references between them, for visualising how notes connect
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+    /// Notes in the graph, ordered by ID
+    pub nodes: Vec<Node>,
+    /// References between notes in the graph, ordered by source and target
+    pub edges: Vec<Edge>,
+}
+
+/// A note in the reference graph
+#[derive(Debug, Serialize)]
+pub struct Node {
+    pub id: NoteId,
+    pub name: String,
+    pub owner: String,
+}
+
+/// A reference from one note to another
+#[derive(Debug, Serialize)]
+pub struct Edge {
+    pub source: NoteId,
+    pub target: NoteId,
+}
+
+impl Graph {
+    /// Renders the graph in the Graphviz DOT language, with each note labelled by its ID, name and owner.
+    /// Notes without any references to or from them are drawn dashed, so orphaned notes stand out
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph notes {\n");
+        for node in &self.nodes {
+            let label = escape_dot(&format!("#{} {}\n{}", node.id, node.name, node.owner));
+            let style = if self.is_orphan(node.id) {
+                ", style=dashed"
+            } else {
+                ""
+            };
+            // Writing to a `String` never fails
+            let _ = writeln!(dot, "    {} [label=\"{label}\"{style}];", node.id);
+        }
+        for edge in &self.edges {
+            let _ = writeln!(dot, "    {} -> {};", edge.source, edge.target);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the graph as a JSON object with `nodes` and `edges` arrays
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the graph can't be serialized
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Checks if a note has no references to or from other notes in the graph
+    fn is_orphan(&self, id: NoteId) -> bool {
+        !self
+            .edges
+            .iter()
+            .any(|edge| (edge.source == id) != (edge.target == id))
+    }
+}
+
+/// Escapes text for a quoted DOT string, turning line breaks into DOT's own `\n`
+fn escape_dot(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -7,32 +7,321 @@
 #![deny(clippy::suspicious)]
 #![deny(clippy::pedantic)]
 
-use std::io;
+use chrono::{DateTime, Utc};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt, io,
+};
 use tabled::Tabled;
 use thiserror::Error;
 
 pub mod app;
 pub mod backends;
+pub mod graph;
+pub mod references;
 pub mod setup;
 pub mod ui;
 
 // More convenient Result type
 pub type Result<T> = std::result::Result<T, NoteError>;
 
-#[derive(Tabled, Debug)]
+/// Identifier of a note. Wide enough that the number of notes is only limited by `max_note_count`
+pub type NoteId = u64;
+
+#[derive(Tabled, Debug, Clone)]
 pub struct Note {
-    pub id: u16,
+    pub id: NoteId,
     pub owner: String,
     pub name: String,
     pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// When the note was moved to the trash, or `None` if it isn't trashed
+    #[tabled(skip)]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 // Partial note data. Displayed in lists and for shallow reads
 #[derive(Tabled)]
 pub struct PartialNote {
-    pub id: u16,
+    pub id: NoteId,
     pub owner: String,
     pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// When the note was moved to the trash, or `None` if it isn't trashed
+    #[tabled(skip)]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Field to sort note listings by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Id,
+    Name,
+    Owner,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Direction to sort note listings in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Which notes to list depending on whether they are in the trash
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrashFilter {
+    /// Only notes that aren't trashed
+    #[default]
+    Exclude,
+    /// Only trashed notes
+    Only,
+    /// Both trashed and regular notes
+    Include,
+}
+
+impl TrashFilter {
+    /// Checks if a note with the given trash state passes the filter
+    #[must_use]
+    pub const fn matches(self, deleted_at: Option<DateTime<Utc>>) -> bool {
+        match self {
+            Self::Exclude => deleted_at.is_none(),
+            Self::Only => deleted_at.is_some(),
+            Self::Include => true,
+        }
+    }
+}
+
+/// Filtering, sorting and pagination of note listings.
+/// The default lists every note outside the trash in ascending ID order
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    /// Only list notes with this tag
+    pub tag: Option<String>,
+    pub trash: TrashFilter,
+    pub sort: SortKey,
+    pub direction: SortDirection,
+    /// Number of notes to skip
+    pub offset: usize,
+    /// Maximum number of notes to return, or all if `None`
+    pub limit: Option<usize>,
+}
+
+impl ListQuery {
+    /// Compares two notes by the sort key and direction. Ties are broken by ascending ID
+    #[must_use]
+    pub fn compare(&self, a: &PartialNote, b: &PartialNote) -> Ordering {
+        let ordering = match self.sort {
+            SortKey::Id => a.id.cmp(&b.id),
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Owner => a.owner.cmp(&b.owner),
+            SortKey::CreatedAt => a.created_at.cmp(&b.created_at),
+            SortKey::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+        };
+        let ordering = match self.direction {
+            SortDirection::Ascending => ordering,
+            SortDirection::Descending => ordering.reverse(),
+        };
+        ordering.then(a.id.cmp(&b.id))
+    }
+
+    /// Sorts and paginates already filtered notes, for backends that can't do it natively
+    #[must_use]
+    pub fn sort_and_paginate(&self, mut notes: Vec<PartialNote>) -> Vec<PartialNote> {
+        notes.sort_by(|a, b| self.compare(a, b));
+        notes
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// A note matching a search query, with an excerpt of its content around the match
+#[derive(Tabled)]
+pub struct SearchResult {
+    #[tabled(inline)]
+    pub note: PartialNote,
+    pub snippet: String,
+}
+
+/// A note in the trash, with the time it will be purged permanently
+#[derive(Tabled)]
+pub struct TrashedNote {
+    #[tabled(inline)]
+    pub note: PartialNote,
+    pub deleted_at: DateTime<Utc>,
+    pub purged_at: DateTime<Utc>,
+}
+
+/// Level of access to a note. Each level includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    /// Read the note and its tags and revisions, and reference it from other notes
+    Read,
+    /// Change the name, content and tags of the note
+    Write,
+    /// Delete, restore and share the note. Only held by the owner and can't be granted
+    Manage,
+}
+
+impl Permission {
+    /// Name of the permission, as stored by backends
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Manage => "manage",
+        }
+    }
+
+    /// Parses a permission stored by a backend
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(Self::Read),
+            "write" => Some(Self::Write),
+            "manage" => Some(Self::Manage),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Named groups of users. A note owned by a group is owned by each of its members
+#[derive(Debug, Clone, Default)]
+pub struct Groups {
+    members: HashMap<String, HashSet<String>>,
+}
+
+impl Groups {
+    /// Adds members to a group, creating the group if it doesn't exist
+    pub fn add(&mut self, group: String, members: impl IntoIterator<Item = String>) {
+        self.members.entry(group).or_default().extend(members);
+    }
+
+    /// Checks if a user is a member of a group. Unknown groups have no members
+    #[must_use]
+    pub fn is_member(&self, group: &str, user: &str) -> bool {
+        self.members
+            .get(group)
+            .is_some_and(|members| members.contains(user))
+    }
+
+    /// Returns the names of all groups a user is a member of, sorted alphabetically
+    #[must_use]
+    pub fn groups_of(&self, user: &str) -> Vec<String> {
+        let mut groups: Vec<String> = self
+            .members
+            .iter()
+            .filter(|(_, members)| members.contains(user))
+            .map(|(group, _)| group.clone())
+            .collect();
+        groups.sort();
+        groups
+    }
+}
+
+/// Access to a note given to a user other than its owner
+#[derive(Tabled, Debug)]
+pub struct Grant {
+    pub note_id: NoteId,
+    pub user: String,
+    pub permission: Permission,
+}
+
+/// How a cascading delete deals with the notes referencing the deleted note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cascade {
+    /// Move the notes referencing the deleted note directly to the trash too
+    Delete,
+    /// Replace the references to the deleted note by a tombstone marker
+    Tombstone,
+}
+
+/// What a cascading delete did to a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CascadeAction {
+    /// Moved the note to the trash
+    Trashed,
+    /// Replaced this many references in the note by a tombstone marker
+    Tombstoned { references: usize },
+}
+
+impl fmt::Display for CascadeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Trashed => f.write_str("moved to the trash"),
+            Self::Tombstoned { references } => {
+                write!(f, "replaced {references} reference(s) by a tombstone")
+            }
+        }
+    }
+}
+
+/// A note changed by a cascading delete
+#[derive(Tabled, Debug)]
+pub struct CascadeChange {
+    pub note_id: NoteId,
+    pub name: String,
+    pub action: CascadeAction,
+}
+
+/// Kind of problem found when checking the integrity of the notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The backend can't parse the stored note
+    Corrupted,
+    /// A reference to a note or name that doesn't exist
+    BrokenReference,
+    /// A reference to a note the owner of the referencing note can't read
+    CrossingReference,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Corrupted => "corrupted",
+            Self::BrokenReference => "broken reference",
+            Self::CrossingReference => "crossing reference",
+        })
+    }
+}
+
+/// A problem with a stored note, found when checking the integrity of the notes
+#[derive(Tabled, Debug)]
+pub struct Issue {
+    pub note_id: NoteId,
+    pub kind: IssueKind,
+    /// The reference or parsing error at fault
+    pub detail: String,
+    /// Whether the problem was repaired
+    pub fixed: bool,
+}
+
+/// A previous version of a note, saved when the note was updated
+#[derive(Tabled, Debug, Clone)]
+pub struct Revision {
+    pub note_id: NoteId,
+    /// Sequential revision number, starting at 1 for the oldest version
+    pub revision: u32,
+    pub name: String,
+    #[tabled(skip)]
+    pub content: String,
+    /// When this version of the note was written
+    pub updated_at: DateTime<Utc>,
 }
 
 /// Trait to be implemented by all backends that manage storing and retrieving notes
@@ -42,42 +331,197 @@ pub trait NoteBackend {
     /// # Errors
     ///
     /// Returns an error if the note could not be inserted
-    fn create(&self, note: Note) -> Result<u16>;
+    fn create(&self, note: Note) -> Result<NoteId>;
 
     /// Fetches the full contents of a note by ID
     ///
     /// # Errors
     ///
     /// Returns an error if the note does not exist or the query fails
-    fn read(&self, id: u16) -> Result<Note>;
+    fn read(&self, id: NoteId) -> Result<Note>;
 
-    /// Fetches a partial view of a note (ID, name, owner) by ID
+    /// Fetches a partial view of a note (ID, name, owner, timestamps) by ID
     ///
     /// # Errors
     ///
     /// Returns an error if the note does not exist or the query fails
-    fn read_partial(&self, id: u16) -> Result<PartialNote>;
+    fn read_partial(&self, id: NoteId) -> Result<PartialNote>;
 
-    /// Updates an existing note, replacing name, owner, and content
+    /// Fetches a partial view of every note with exactly the given name that is owned by one of `owners`
+    /// or shared with `grantee`, sorted by ID. Notes in the trash are included
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn find_by_name(
+        &self,
+        name: &str,
+        owners: &[String],
+        grantee: &str,
+    ) -> Result<Vec<PartialNote>>;
+
+    /// Updates an existing note, replacing name, owner, content and modification time.
+    /// The creation time and trash state of the stored note are kept as is, and the replaced version is saved as
+    /// a new revision
     ///
     /// # Errors
     ///
     /// Returns an error if the update fails or the note is not found
     fn update(&self, note: Note) -> Result<()>;
 
-    /// Deletes a note by ID from the backend
+    /// Permanently deletes a note by ID from the backend, along with its tags, revisions, grants and references
     ///
     /// # Errors
     ///
     /// Returns an error if the note is not found or the deletion fails
-    fn delete(&self, id: u16) -> Result<()>;
+    fn delete(&self, id: NoteId) -> Result<()>;
+
+    /// Moves a note to the trash at `deleted_at`, or restores it from the trash if `None`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the note is not found or the change could not be stored
+    fn set_deleted(&self, id: NoteId, deleted_at: Option<DateTime<Utc>>) -> Result<()>;
+
+    /// Gives a user access to a note, replacing any access they were already granted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the note is not found or the grant could not be stored
+    fn set_grant(&self, id: NoteId, user: &str, permission: Permission) -> Result<()>;
+
+    /// Takes away the access a user was granted to a note
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the user has no grant on the note or the grant could not be removed
+    fn remove_grant(&self, id: NoteId, user: &str) -> Result<()>;
+
+    /// Fetches all grants on a note, sorted by user
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn list_grants(&self, id: NoteId) -> Result<Vec<Grant>>;
 
-    /// Returns a list of all notes in the backend with partial details (ID, name, owner)
+    /// Returns a filtered, sorted and paginated list of notes in the backend with partial details
+    /// (ID, name, owner, timestamps)
     ///
     /// # Errors
     ///
     /// Returns an error if the query fails
-    fn list(&self) -> Result<Vec<PartialNote>>;
+    fn list(&self, query: &ListQuery) -> Result<Vec<PartialNote>>;
+
+    /// Returns the IDs of every stored note in ascending order, including notes that can't be parsed
+    /// and are left out of `list`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn list_ids(&self) -> Result<Vec<NoteId>>;
+
+    /// Moves a note that can't be parsed out of the backend, keeping a copy of the stored record for manual recovery.
+    /// Its tags, revisions, grants and references are deleted, so the ID can be reused
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the note does not exist or can't be moved
+    fn quarantine(&self, id: NoteId) -> Result<()>;
+
+    /// Replaces all tags attached to a note. An empty slice removes every tag
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the note is not found or the tags could not be stored
+    fn set_tags(&self, id: NoteId, tags: &[String]) -> Result<()>;
+
+    /// Fetches all tags attached to a note, sorted alphabetically
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn read_tags(&self, id: NoteId) -> Result<Vec<String>>;
+
+    /// Replaces the notes a note references in the reference index, along with the names it references notes by,
+    /// whether or not they resolve. Duplicates are ignored
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the note is not found or the references could not be stored
+    fn set_links(&self, id: NoteId, targets: &[NoteId], names: &[String]) -> Result<()>;
+
+    /// Looks up the IDs of all notes referencing a note in the reference index, in ascending order.
+    /// A note referencing itself is included
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn backlinks(&self, id: NoteId) -> Result<Vec<NoteId>>;
+
+    /// Looks up the IDs of all notes referencing any of the given names in the reference index, in ascending order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn name_backlinks(&self, names: &[&str]) -> Result<Vec<NoteId>>;
+
+    /// Checks if the reference index covers every note. It doesn't for backends created before the index existed,
+    /// until it has been marked with `mark_links_indexed` and filled with `set_links`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn links_indexed(&self) -> Result<bool>;
+
+    /// Marks the reference index as covering every note. Backends may ignore `set_links` until this is called
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the mark could not be stored
+    fn mark_links_indexed(&self) -> Result<()>;
+
+    /// Searches the name and content of all notes for every whitespace-separated term in `query`,
+    /// ignoring case. Matched terms are wrapped in `[` and `]` in the snippet
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn search(&self, query: &str) -> Result<Vec<SearchResult>>;
+
+    /// Returns all saved revisions of a note, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    fn list_revisions(&self, id: NoteId) -> Result<Vec<Revision>>;
+
+    /// Fetches a single revision of a note
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the revision does not exist or the query fails
+    fn read_revision(&self, id: NoteId, revision: u32) -> Result<Revision>;
+
+    /// Starts a transaction. Until it is committed or rolled back, no other writer can modify the backend
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a transaction is already active or the backend can't be locked
+    fn begin(&self) -> Result<()>;
+
+    /// Makes all changes since `begin` permanent and ends the transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no transaction is active or the changes could not be committed
+    fn commit(&self) -> Result<()>;
+
+    /// Undoes all changes since `begin` and ends the transaction
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no transaction is active or the changes could not be undone
+    fn rollback(&self) -> Result<()>;
 }
 
 // Enum for all possible validation or repository-related errors
@@ -91,6 +535,19 @@ pub enum NoteError {
 
     #[error(transparent)]
     Menu(#[from] MenuError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+// Enum for all possible errors in configuration files
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed reading groups file")]
+    GroupsFileReadError(io::Error),
+
+    #[error("Line {0} of the groups file is invalid. Expected 'group: member, member'")]
+    GroupsFileInvalid(usize),
 }
 
 // Enum for all possible menu input errors
@@ -99,10 +556,10 @@ pub enum MenuError {
     #[error("Failed to read from stdin: {0}")]
     StdinReadError(io::Error),
 
-    #[error("Couldn't convert '{0}' to a number. Please enter a number 1-6")]
+    #[error("Couldn't convert '{0}' to a number. Please enter a number 1-{max}", max = ui::io::ALL_MENU_OPTIONS.len())]
     ParseError(String),
 
-    #[error("Couldn't convert '{0}' to a MenuOption. Please enter a number 1-6")]
+    #[error("Couldn't convert '{0}' to a MenuOption. Please enter a number 1-{max}", max = ui::io::ALL_MENU_OPTIONS.len())]
     InvalidOption(u8),
 
     #[error("Failed writing to stdout")]
@@ -124,20 +581,56 @@ pub enum NoteValidationError {
     #[error("Content is too large. Max: {max}, Got: {got}")]
     ContentTooLarge { max: u16, got: usize },
 
+    #[error("Tag '{0}' is invalid. Tags can't be empty or contain whitespace or commas")]
+    TagInvalid(String),
+
+    #[error("Tag is too large. Max: {max}, Got: {got}")]
+    TagTooLarge { max: u8, got: usize },
+
+    #[error("Search query is empty")]
+    QueryEmpty,
+
     #[error("You've hit the limit for how many notes you can have. Max: {max}")]
-    NoteCountLimit { max: u16 },
+    NoteCountLimit { max: u64 },
+
+    #[error("Sorry! You're not allowed to do that with the note with ID: {0}")]
+    PermissionDenied(NoteId),
+
+    #[error("Username is empty")]
+    UserEmpty,
+
+    #[error("You're not a member of the group: {0}")]
+    NotGroupMember(String),
 
-    #[error("Sorry! You're not the owner the note with ID: {0}")]
-    PermissionDenied(u16),
+    #[error("The '{0}' permission can't be granted to other users")]
+    PermissionNotGrantable(Permission),
 
     #[error("Note not found with ID: {0}")]
-    NoteNotFound(u16),
+    NoteNotFound(NoteId),
+
+    #[error("Note with ID {0} is in the trash. Restore it first")]
+    NoteTrashed(NoteId),
+
+    #[error("Note with ID {0} is not in the trash")]
+    NoteNotTrashed(NoteId),
 
     #[error("Note is referenced by: {0:?}")]
-    NoteIsReferenced(Vec<u16>),
+    NoteIsReferenced(Vec<NoteId>),
+
+    #[error("Note is referenced by notes owned by someone else: {0:?}")]
+    ReferencedByOthers(Vec<NoteId>),
 
     #[error("Reference not found with ID: {0}")]
-    ReferenceNotFound(u16),
+    ReferenceNotFound(NoteId),
+
+    #[error("Note with ID {id} has no heading: {heading}")]
+    HeadingNotFound { id: NoteId, heading: String },
+
+    #[error("Reference not found with name: {0}")]
+    ReferenceNameNotFound(String),
+
+    #[error("Several notes are named '{name}': {ids:?}. Reference one of them by ID instead")]
+    ReferenceNameAmbiguous { name: String, ids: Vec<NoteId> },
 }
 
 // Enum for all possible repository/backend errors
@@ -146,9 +639,12 @@ pub enum BackendError {
     #[error("Database file not found")]
     DatabaseCreationError,
 
-    #[error("Failed creating `notes` table in database")]
+    #[error("Failed creating tables in database")]
     TableCreationError,
 
+    #[error("Failed upgrading `notes` table to the current schema")]
+    SchemaMigrationError,
+
     #[error("Failed creating directory for notes")]
     DirectoryCreationError(io::Error),
 
@@ -167,12 +663,30 @@ pub enum BackendError {
     #[error("Note is improperly formatted. Failed reading all fields")]
     NoteCorrupted,
 
+    #[error("Notes document is invalid: {0}")]
+    InvalidDocument(serde_json::Error),
+
+    #[error("Stored notes have unsupported schema version {0}")]
+    UnsupportedSchemaVersion(u32),
+
     #[error("Note already exists")]
     Duplicate,
 
     #[error("Database is locked or busy")]
     DatabaseBusy,
 
+    #[error("Failed locking the notes directory")]
+    LockError(io::Error),
+
+    #[error("Failed writing or restoring the transaction journal")]
+    JournalError(io::Error),
+
+    #[error("A transaction is already active")]
+    TransactionAlreadyActive,
+
+    #[error("No transaction is active")]
+    NoActiveTransaction,
+
     #[error("Database corruption or file I/O error")]
     DatabaseCorruptOrIo,
 
@@ -189,11 +703,17 @@ pub enum BackendError {
     SchemaChanged,
 
     #[error("No notes with ID: {0}")]
-    NoteNotFound(u16),
+    NoteNotFound(NoteId),
 
     #[error("No notes found")]
     NoNotesFound,
 
+    #[error("No revision {revision} of note with ID: {id}")]
+    RevisionNotFound { id: NoteId, revision: u32 },
+
+    #[error("User '{user}' has no access granted to note with ID: {id}")]
+    GrantNotFound { id: NoteId, user: String },
+
     #[error("Insufficient permissions")]
     PermissionDenied,
 
@@ -1,16 +1,45 @@
-use log::error;
+use log::{error, info};
 use noters::{
-    setup::{arguments, logging},
+    setup::{
+        arguments::{self, Mode},
+        logging,
+    },
     ui::io,
 };
 
 fn main() {
     logging::setup_log();
     dotenv::dotenv().ok();
-    let service = arguments::handle_args().unwrap_or_else(|e| {
+    let (service, mode) = arguments::handle_args().unwrap_or_else(|e| {
         error!("Failed initializing backend: {e}");
         panic!()
     });
 
-    io::run(&service)
+    match service.index_links() {
+        Ok(true) => info!("Indexed the references of existing notes"),
+        Ok(false) => {}
+        Err(e) => error!("Failed indexing references of existing notes: {e}"),
+    }
+
+    match service.purge_expired_notes() {
+        Ok(purged) if !purged.is_empty() => {
+            info!("Purged expired notes from the trash: {purged:?}")
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed purging expired notes from the trash: {e}"),
+    }
+
+    match mode {
+        Mode::Menu => io::run(&service),
+        Mode::Check { fix } => {
+            if !io::check(&service, fix) {
+                std::process::exit(1);
+            }
+        }
+        Mode::Graph { format, root } => {
+            if !io::export_graph(&service, root, format) {
+                std::process::exit(1);
+            }
+        }
+    }
 }
@@ -0,0 +1,173 @@
+use crate::NoteId;
+
+use std::ops::Range;
+
+/// A reference to another note in the content of a note, written as `[[id]]` or `[[Note name]]`.
+/// Either can be followed by `#Heading` to only reference a section of the note, and then by `|display text`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// Byte range of the whole reference in the content, brackets included
+    pub span: Range<usize>,
+    /// The referenced note
+    pub target: Target,
+    /// Markdown heading of the referenced section, or `None` for the whole note
+    pub heading: Option<String>,
+    /// Text to show instead of the name of the referenced note
+    pub display: Option<String>,
+}
+
+impl Reference {
+    /// Plain text to show in place of the reference when it can't be linked: its display text,
+    /// or the ID or name it points at
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.display.clone().unwrap_or_else(|| {
+            let target = match &self.target {
+                Target::Id(id) => format!("#{id}"),
+                Target::Name(name) => name.clone(),
+            };
+            match &self.heading {
+                Some(heading) => format!("{target}#{heading}"),
+                None => target,
+            }
+        })
+    }
+}
+
+/// How a reference points at a note
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// By ID, when the target is only digits
+    Id(NoteId),
+    /// By name, which is resolved among the notes the reader can read
+    Name(String),
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "#{id}"),
+            Self::Name(name) => write!(f, "'{name}'"),
+        }
+    }
+}
+
+/// Finds every reference in a text, in order of appearance.
+///
+/// References can appear anywhere, e.g. in `([[3]])` or `[[3]].`, but can't span multiple lines.
+/// Brackets that don't form a valid reference are left alone.
+#[must_use]
+pub fn parse(text: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find("[[").map(|i| offset + i) {
+        let inner_start = start + 2;
+        let Some(inner_end) = text[inner_start..].find("]]").map(|i| inner_start + i) else {
+            break;
+        };
+
+        if let Some((target, heading, display)) = parse_inner(&text[inner_start..inner_end]) {
+            let end = inner_end + 2;
+            references.push(Reference {
+                span: start..end,
+                target,
+                heading,
+                display,
+            });
+            offset = end;
+        } else {
+            // The reference may start at a later bracket, like in `[[[3]]`
+            offset = start + 1;
+        }
+    }
+    references
+}
+
+/// Parses the text between the brackets of a reference: an ID or a name, optionally followed by `#` and a heading,
+/// and then by `|` and display text
+fn parse_inner(inner: &str) -> Option<(Target, Option<String>, Option<String>)> {
+    if inner.contains(['\n', '[']) {
+        return None;
+    }
+
+    let (target, display) = match inner.split_once('|') {
+        Some((target, display)) => (target, Some(display.trim())),
+        None => (inner, None),
+    };
+    let (target, heading) = match target.split_once('#') {
+        Some((target, heading)) if !heading.trim().is_empty() => (target, Some(heading.trim())),
+        Some(_) => return None,
+        None => (target, None),
+    };
+
+    // Only plain digits make an ID, since `parse` would also accept a leading `+`.
+    // Anything else is a name, as long as it isn't padded with whitespace
+    let target = if !target.is_empty() && target.bytes().all(|b| b.is_ascii_digit()) {
+        Target::Id(target.parse().ok()?)
+    } else if !target.is_empty() && target.trim() == target {
+        Target::Name(target.to_string())
+    } else {
+        return None;
+    };
+
+    Some((
+        target,
+        heading.map(str::to_string),
+        display.filter(|d| !d.is_empty()).map(str::to_string),
+    ))
+}
+
+/// Finds the section under a Markdown heading like `## Heading`, matched without regard to case.
+///
+/// The section runs until the next heading of the same or a higher level, and doesn't include the heading itself.
+/// Lines in fenced code blocks are never headings.
+///
+/// # Returns
+///
+/// The section with surrounding blank lines removed, or `None` if there is no such heading
+#[must_use]
+pub fn section<'a>(content: &'a str, heading: &str) -> Option<&'a str> {
+    let mut start = None;
+    let mut in_fence = false;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        let Some((level, text)) = parse_heading(line).filter(|_| !in_fence) else {
+            continue;
+        };
+
+        match start {
+            Some((section_level, section_start)) if level <= section_level => {
+                return Some(content[section_start..line_start].trim_matches('\n'));
+            }
+            None if text.to_lowercase() == heading.to_lowercase() => {
+                start = Some((level, offset));
+            }
+            _ => {}
+        }
+    }
+    start.map(|(_, section_start)| content[section_start..].trim_matches('\n'))
+}
+
+/// Parses a Markdown ATX heading, like `## Heading ##`, into its level and text
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let line = line.trim_end();
+    let level = line.bytes().take_while(|&b| b == b'#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+
+    let text = &line[level..];
+    if !text.is_empty() && !text.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some((level, text.trim_end_matches('#').trim()))
+}
@@ -1,2 +1,3 @@
 pub mod arguments;
+pub mod groups;
 pub mod logging;
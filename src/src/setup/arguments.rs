@@ -1,12 +1,27 @@
 use crate::app::NoteService;
-use crate::backends::{FilesystemBackend, SqliteBackend};
-use crate::{NoteBackend, Result};
+use crate::backends::{FilesystemBackend, InMemoryBackend, JsonBackend, SqliteBackend};
+use crate::setup::groups;
+use crate::{Groups, NoteBackend, NoteId, Result};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about)]
 struct Args {
+    /// Whether to open the menu, check the integrity of the notes, or print the graph of references between notes
+    #[arg(value_enum, default_value_t = Command::Menu)]
+    command: Command,
+    /// Repair the problems found by `check` in notes the user owns, quarantining corrupted notes and unlinking bad
+    /// references
+    #[arg(long)]
+    fix: bool,
+    /// Output format of `graph`
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    format: GraphFormat,
+    /// Only include the notes `graph` can reach from the note with this ID
+    #[arg(long)]
+    from: Option<NoteId>,
     #[arg(short, long)]
     user: String,
     #[arg(long, default_value_t = 32)]
@@ -14,11 +29,50 @@ struct Args {
     #[arg(long, default_value_t = 1024)]
     max_content_size: u16,
     #[arg(long, default_value_t = 100)]
-    max_note_count: u16,
+    max_note_count: u64,
+    /// Days notes stay in the trash before they are purged
+    #[arg(long, default_value_t = 30)]
+    trash_retention_days: u16,
+    /// File listing groups of users that can own notes together
+    #[arg(long)]
+    groups: Option<PathBuf>,
+    /// How many levels of nested note references to expand when reading a note, or 0 to not expand them
+    #[arg(long, default_value_t = 3)]
+    max_reference_depth: u8,
     #[command(subcommand)]
     backend: Backend,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Command {
+    Menu,
+    Check,
+    Graph,
+}
+
+/// Output format of the reference graph
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT language
+    Dot,
+    /// JSON object with `nodes` and `edges` arrays
+    Json,
+}
+
+/// What to do once the backend is open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Run the interactive menu
+    Menu,
+    /// Check every note for broken references and corrupted records, and repair them if `fix` is set
+    Check { fix: bool },
+    /// Print the graph of references between notes, or only the notes reachable from `root`
+    Graph {
+        format: GraphFormat,
+        root: Option<NoteId>,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum Backend {
     Filesystem {
@@ -29,32 +83,60 @@ enum Backend {
         #[arg(short, long)]
         path: String,
     },
+    /// Store all notes in a single JSON file
+    Json {
+        #[arg(short, long)]
+        path: String,
+    },
+    /// Keep notes in memory only, discarding them on exit
+    Memory,
 }
 
 /// Parses command-line arguments and initializes a `NoteService` based on the provided arguments.
 ///
 /// # Returns
 ///
-/// A `NoteService` instance initialized with the parsed arguments.
+/// A `NoteService` instance initialized with the parsed arguments, and the mode to run it in.
 ///
 /// # Errors
 ///
 /// - `NoteValidationError::UsernameTooLong` if the username length exceeds 32 characters
+/// - `ConfigError` variants if the groups file can't be loaded
 /// - Tries creating a `NoteBackend` instance based on the specified backend type and initializes a `NoteService` with it. Any errors are forwarded
-pub fn handle_args() -> Result<NoteService> {
+pub fn handle_args() -> Result<(NoteService, Mode)> {
     let args = Args::parse();
 
+    let groups = match &args.groups {
+        Some(path) => groups::load_groups(path)?,
+        None => Groups::default(),
+    };
+
     // Allow any struct that implements NoteBackend, and store on heap because size is unknown at compile time
     let repo: Box<dyn NoteBackend> = match args.backend {
         Backend::Filesystem { path } => Box::new(FilesystemBackend::new(&path)?),
         Backend::Sqlite { path } => Box::new(SqliteBackend::new(&path)?),
+        Backend::Json { path } => Box::new(JsonBackend::new(&path)?),
+        Backend::Memory => Box::new(InMemoryBackend::new()),
     };
 
-    Ok(NoteService::new(
+    let service = NoteService::new(
         repo,
         args.user,
         args.max_name_size,
         args.max_content_size,
         args.max_note_count,
-    ))
+        args.trash_retention_days,
+        groups,
+    )
+    .with_max_reference_depth(args.max_reference_depth);
+
+    let mode = match args.command {
+        Command::Menu => Mode::Menu,
+        Command::Check => Mode::Check { fix: args.fix },
+        Command::Graph => Mode::Graph {
+            format: args.format,
+            root: args.from,
+        },
+    };
+    Ok((service, mode))
 }
@@ -0,0 +1,45 @@
+use crate::{ConfigError, Groups, NoteError, Result};
+
+use log::debug;
+use std::{fs, path::Path};
+
+/// Loads the group registry from a file with one group per line, e.g.
+///
+/// ```text
+/// # Lines starting with '#' are comments
+/// Norske Nøkkelsnikere: alice, bob
+/// ```
+///
+/// Group names can contain spaces, and members are separated by commas. Empty lines are skipped.
+///
+/// # Errors
+///
+/// - `ConfigError::GroupsFileReadError` if the file can't be read
+/// - `ConfigError::GroupsFileInvalid` if a line has no `:` or an empty group name
+pub fn load_groups(path: &Path) -> Result<Groups> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| NoteError::Config(ConfigError::GroupsFileReadError(e)))?;
+
+    let mut groups = Groups::default();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (group, members) = line
+            .split_once(':')
+            .filter(|(group, _)| !group.trim().is_empty())
+            .ok_or(NoteError::Config(ConfigError::GroupsFileInvalid(i + 1)))?;
+        groups.add(
+            group.trim().to_string(),
+            members
+                .split(',')
+                .map(str::trim)
+                .filter(|member| !member.is_empty())
+                .map(str::to_string),
+        );
+    }
+    debug!("Loaded groups from {}", path.display());
+    Ok(groups)
+}
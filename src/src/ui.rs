@@ -1,4 +1,8 @@
 pub mod cli;
 pub mod io;
 
-pub use crate::{MenuError, NoteError, PartialNote, Result};
+pub use crate::{
+    Cascade, CascadeChange, Grant, Issue, ListQuery, MenuError, NoteError, NoteId,
+    NoteValidationError, PartialNote, Permission, Result, Revision, SearchResult, SortDirection,
+    SortKey, TrashedNote,
+};
@@ -1,4 +1,7 @@
-use super::{MenuError, NoteError, PartialNote, Result};
+use super::{
+    CascadeChange, Grant, Issue, MenuError, NoteError, PartialNote, Result, Revision, SearchResult,
+    TrashedNote,
+};
 
 use crate::ui::io::IO;
 use colored::Colorize;
@@ -96,6 +99,72 @@ impl IO for Cli {
         println!("{table}\n");
     }
 
+    /// Renders a table of search results in `psql` style to stdout.
+    ///
+    /// # Parameters
+    ///
+    /// - `results`: A vector of `SearchResult` items to tabulate.
+    fn show_search_results(&self, results: Vec<SearchResult>) {
+        let mut table = Table::new(results);
+        table.with(Style::psql());
+        println!("{table}\n");
+    }
+
+    /// Renders a table of note revisions in `psql` style to stdout.
+    ///
+    /// # Parameters
+    ///
+    /// - `revisions`: A vector of `Revision` items to tabulate.
+    fn show_revisions_list(&self, revisions: Vec<Revision>) {
+        let mut table = Table::new(revisions);
+        table.with(Style::psql());
+        println!("{table}\n");
+    }
+
+    /// Renders a table of trashed notes in `psql` style to stdout.
+    ///
+    /// # Parameters
+    ///
+    /// - `trashed_notes`: A vector of `TrashedNote` items to tabulate.
+    fn show_trash_list(&self, trashed_notes: Vec<TrashedNote>) {
+        let mut table = Table::new(trashed_notes);
+        table.with(Style::psql());
+        println!("{table}\n");
+    }
+
+    /// Renders a table of note grants in `psql` style to stdout.
+    ///
+    /// # Parameters
+    ///
+    /// - `grants`: A vector of `Grant` items to tabulate.
+    fn show_grants_list(&self, grants: Vec<Grant>) {
+        let mut table = Table::new(grants);
+        table.with(Style::psql());
+        println!("{table}\n");
+    }
+
+    /// Renders a table of notes changed by a cascading delete in `psql` style to stdout.
+    ///
+    /// # Parameters
+    ///
+    /// - `changes`: A vector of `CascadeChange` items to tabulate.
+    fn show_cascade_changes(&self, changes: Vec<CascadeChange>) {
+        let mut table = Table::new(changes);
+        table.with(Style::psql());
+        println!("{table}\n");
+    }
+
+    /// Renders a table of problems found in the notes in `psql` style to stdout.
+    ///
+    /// # Parameters
+    ///
+    /// - `issues`: A vector of `Issue` items to tabulate.
+    fn show_issues_list(&self, issues: Vec<Issue>) {
+        let mut table = Table::new(issues);
+        table.with(Style::psql());
+        println!("{table}\n");
+    }
+
     /// Prints a bolded title followed by a blank line.
     ///
     /// # Parameters
@@ -1,5 +1,10 @@
-use super::{MenuError, NoteError, PartialNote, Result};
+use super::{
+    Cascade, CascadeChange, Grant, Issue, ListQuery, MenuError, NoteError, NoteId,
+    NoteValidationError, PartialNote, Permission, Result, Revision, SearchResult, SortDirection,
+    SortKey, TrashedNote,
+};
 use crate::app::NoteService;
+use crate::setup::arguments::GraphFormat;
 use crate::ui::cli;
 
 use colored::Colorize;
@@ -49,6 +54,48 @@ pub trait IO {
     /// - `table`: A vector of `PartialNote` structs to display in rows
     fn show_notes_list(&self, table: Vec<PartialNote>);
 
+    /// Render a list of matching notes with snippets of their content
+    ///
+    /// # Parameters
+    ///
+    /// - `results`: A vector of `SearchResult` structs to display in rows
+    fn show_search_results(&self, results: Vec<SearchResult>);
+
+    /// Render a list of previous versions of a note
+    ///
+    /// # Parameters
+    ///
+    /// - `revisions`: A vector of `Revision` structs to display in rows
+    fn show_revisions_list(&self, revisions: Vec<Revision>);
+
+    /// Render a list of notes in the trash
+    ///
+    /// # Parameters
+    ///
+    /// - `trashed_notes`: A vector of `TrashedNote` structs to display in rows
+    fn show_trash_list(&self, trashed_notes: Vec<TrashedNote>);
+
+    /// Render a list of users a note is shared with
+    ///
+    /// # Parameters
+    ///
+    /// - `grants`: A vector of `Grant` structs to display in rows
+    fn show_grants_list(&self, grants: Vec<Grant>);
+
+    /// Render a list of notes changed by a cascading delete
+    ///
+    /// # Parameters
+    ///
+    /// - `changes`: A vector of `CascadeChange` structs to display in rows
+    fn show_cascade_changes(&self, changes: Vec<CascadeChange>);
+
+    /// Render a list of problems found when checking the notes
+    ///
+    /// # Parameters
+    ///
+    /// - `issues`: A vector of `Issue` structs to display in rows
+    fn show_issues_list(&self, issues: Vec<Issue>);
+
     /// Show arbitrary text
     ///
     /// # Parameters
@@ -57,6 +104,9 @@ pub trait IO {
     fn show_text(&self, msg: &str);
 }
 
+/// Number of notes shown per page when listing notes
+const PAGE_SIZE: usize = 10;
+
 /// CRUD and listing actions available in the menu
 #[derive(Debug, Clone, Copy)]
 pub enum MenuOption {
@@ -66,16 +116,28 @@ pub enum MenuOption {
     Delete = 4,
     List = 5,
     AddFlag = 6,
+    ListByTag = 7,
+    Search = 8,
+    History = 9,
+    Trash = 10,
+    Share = 11,
+    Unshare = 12,
 }
 
 /// All menu options in display order
-pub const ALL_MENU_OPTIONS: [MenuOption; 6] = [
+pub const ALL_MENU_OPTIONS: [MenuOption; 12] = [
     MenuOption::Create,
     MenuOption::Read,
     MenuOption::Update,
     MenuOption::Delete,
     MenuOption::List,
     MenuOption::AddFlag,
+    MenuOption::ListByTag,
+    MenuOption::Search,
+    MenuOption::History,
+    MenuOption::Trash,
+    MenuOption::Share,
+    MenuOption::Unshare,
 ];
 
 /// Convert a numeric choice into a `MenuOption`
@@ -94,6 +156,12 @@ impl TryFrom<u8> for MenuOption {
             4 => Ok(Self::Delete),
             5 => Ok(Self::List),
             6 => Ok(Self::AddFlag),
+            7 => Ok(Self::ListByTag),
+            8 => Ok(Self::Search),
+            9 => Ok(Self::History),
+            10 => Ok(Self::Trash),
+            11 => Ok(Self::Share),
+            12 => Ok(Self::Unshare),
             _ => Err(()),
         }
     }
@@ -109,6 +177,12 @@ impl fmt::Display for MenuOption {
             Self::Delete => "Delete note",
             Self::List => "List notes",
             Self::AddFlag => "Add note with flag",
+            Self::ListByTag => "List notes by tag",
+            Self::Search => "Search notes",
+            Self::History => "Note history",
+            Self::Trash => "Trash",
+            Self::Share => "Share note",
+            Self::Unshare => "Unshare note",
         };
         write!(f, "({}) {}", *self as u8, label)
     }
@@ -129,6 +203,12 @@ fn handle_menu_option(io: &impl IO, service: &NoteService, option: MenuOption) {
         MenuOption::Delete => handle_delete(io, service),
         MenuOption::List => handle_list(io, service),
         MenuOption::AddFlag => handle_add_flag(service),
+        MenuOption::ListByTag => handle_list_by_tag(io, service),
+        MenuOption::Search => handle_search(io, service),
+        MenuOption::History => handle_history(io, service),
+        MenuOption::Trash => handle_trash(io, service),
+        MenuOption::Share => handle_share(io, service),
+        MenuOption::Unshare => handle_unshare(io, service),
     }
 }
 
@@ -155,6 +235,68 @@ pub fn run(service: &NoteService) {
     }
 }
 
+/// Check the integrity of every note and show the problems found, repairing them if `fix` is set
+///
+/// # Returns
+///
+/// Whether every note is fine, or was repaired
+#[must_use]
+pub fn check(service: &NoteService, fix: bool) -> bool {
+    let io = cli::Cli;
+
+    let issues = match service.check(fix) {
+        Ok(issues) => issues,
+        Err(e) => {
+            error!("Failed checking notes: {e}");
+            return false;
+        }
+    };
+    if issues.is_empty() {
+        info!("No problems found");
+        return true;
+    }
+
+    let count = issues.len();
+    let fixed = issues.iter().filter(|issue| issue.fixed).count();
+    io.show_issues_list(issues);
+    if fix {
+        info!("Fixed {fixed} of {count} problems");
+    } else {
+        warn!("Found {count} problems. Run with --fix to repair them");
+    }
+    fixed == count
+}
+
+/// Print the graph of references between the notes the user can read, or only the ones reachable from `root`
+///
+/// # Returns
+///
+/// Whether the graph could be built and printed
+#[must_use]
+pub fn export_graph(service: &NoteService, root: Option<NoteId>, format: GraphFormat) -> bool {
+    let io = cli::Cli;
+
+    let graph = match service.reference_graph(root) {
+        Ok(graph) => graph,
+        Err(e) => {
+            error!("Failed building the reference graph: {e}");
+            return false;
+        }
+    };
+    let output = match format {
+        GraphFormat::Dot => graph.to_dot(),
+        GraphFormat::Json => match graph.to_json() {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed serializing the reference graph: {e}");
+                return false;
+            }
+        },
+    };
+    io.show_text(output.trim_end());
+    true
+}
+
 /// Try parsing input as `MenuOption` or return an error
 ///
 /// # Parameters
@@ -220,7 +362,10 @@ fn handle_create(io: &impl IO, service: &NoteService) {
         }
     };
 
-    match service.create_note(name, content) {
+    let tags = get_tags(io, service);
+    let owner = get_owner(io, service);
+
+    match service.create_note(name, content, &tags, owner) {
         Ok(id) => info!("Note saved with ID: {id}\n"),
         Err(e) => error!("{e}\n"),
     }
@@ -239,10 +384,10 @@ fn handle_create(io: &impl IO, service: &NoteService) {
 fn handle_read(io: &impl IO, service: &NoteService) {
     io.show_title("Read note");
 
-    let id: u16 = loop {
+    let id: NoteId = loop {
         io.show_text("ID:");
         let input = io.get_input().expect("Failed getting note ID");
-        match input.parse::<u16>() {
+        match input.parse::<NoteId>() {
             Ok(id) => {
                 trace!("Got valid ID: {id}\n");
                 break id;
@@ -254,11 +399,35 @@ fn handle_read(io: &impl IO, service: &NoteService) {
     match service.read_note(id) {
         Ok(note) => {
             let title_text = format!("#{}: {}", note.id, note.name).bold();
+            let timestamps_text = format!(
+                "Created: {} | Updated: {}",
+                note.created_at, note.updated_at
+            )
+            .dimmed();
 
             io.show_text(&"-".repeat(20));
             io.show_text(&title_text);
+            io.show_text(&timestamps_text);
+            match service.read_tags(note.id) {
+                Ok(tags) if !tags.is_empty() => {
+                    io.show_text(&format!("Tags: {}", tags.join(", ")).dimmed());
+                }
+                Ok(_) => {}
+                Err(e) => error!("{e}\n"),
+            }
             io.show_text("");
             io.show_text(&note.content);
+            match service.backlinks(note.id) {
+                Ok(backlinks) if !backlinks.is_empty() => {
+                    io.show_text("");
+                    io.show_text(&"Referenced by:".dimmed());
+                    for backlink in backlinks {
+                        io.show_text(&format!("- #{}: {}", backlink.id, backlink.name).dimmed());
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => error!("{e}\n"),
+            }
             io.show_text(&"-".repeat(20));
             io.show_text("");
         }
@@ -282,7 +451,7 @@ fn handle_update(io: &impl IO, service: &NoteService) {
     let mut note = loop {
         io.show_text("ID:");
         let input = io.get_input().expect("Failed getting note ID");
-        let id = match input.parse::<u16>() {
+        let id = match input.parse::<NoteId>() {
             Ok(id) => id,
             Err(e) => {
                 error!("{e}");
@@ -322,16 +491,22 @@ fn handle_update(io: &impl IO, service: &NoteService) {
         }
     };
 
+    match service.read_tags(note.id) {
+        Ok(tags) => io.show_text(&format!("Current tags: {}", tags.join(", "))),
+        Err(e) => error!("{e}\n"),
+    }
+    let tags = get_tags(io, service);
+
     note.name = name;
     note.content = content;
 
-    match service.update_note(note) {
+    match service.update_note(note, &tags) {
         Ok(()) => info!("Successfully updated note\n"),
         Err(e) => error!("{e}\n"),
     }
 }
 
-/// Prompt for note ID, confirm deletion, and delete
+/// Prompt for note ID, confirm deletion, and move the note to the trash
 ///
 /// # Parameters
 ///
@@ -344,49 +519,538 @@ fn handle_update(io: &impl IO, service: &NoteService) {
 fn handle_delete(io: &impl IO, service: &NoteService) {
     io.show_title("Delete note");
 
-    let id: u16 = loop {
+    let id: NoteId = loop {
         io.show_text("ID:");
         let input = io.get_input().expect("Failed getting note ID");
-        match input.parse::<u16>() {
+        match input.parse::<NoteId>() {
             Ok(id) => break id,
             Err(e) => error!("{e}\n"),
         }
     };
 
+    if !get_confirmation(io) {
+        info!("Exiting. Not deleting note with ID: {id}\n");
+        return;
+    }
+
+    let backlinks = match service.delete_note(id) {
+        Ok(()) => {
+            info!("Moved note with ID {id} to the trash\n");
+            return;
+        }
+        Err(NoteError::Validation(NoteValidationError::NoteIsReferenced(backlinks))) => backlinks,
+        Err(e) => {
+            error!("{e}\n");
+            return;
+        }
+    };
+
+    // Offer to deal with the referencing notes instead
+    let cascade = loop {
+        io.show_text(&format!(
+            "Note is referenced by: {backlinks:?}. (d)elete them too, (t)ombstone the references, or (q)uit:"
+        ));
+        let input = io.get_input().expect("Failed getting cascade mode");
+        match input.to_lowercase().as_str() {
+            "d" | "delete" => break Cascade::Delete,
+            "t" | "tombstone" => break Cascade::Tombstone,
+            "q" | "quit" => {
+                info!("Exiting. Not deleting note with ID: {id}\n");
+                return;
+            }
+            _ => warn!("Invalid input. Please enter 'd', 't' or 'q'\n"),
+        }
+    };
+    if !get_confirmation(io) {
+        info!("Exiting. Not deleting note with ID: {id}\n");
+        return;
+    }
+
+    match service.delete_note_cascading(id, cascade) {
+        Ok(changes) => {
+            io.show_cascade_changes(changes);
+            info!("Moved note with ID {id} to the trash\n");
+        }
+        Err(e) => error!("{e}\n"),
+    }
+}
+
+/// Ask the user to confirm a destructive action
+///
+/// # Parameters
+///
+/// - `io`: I/O implementation
+///
+/// # Returns
+///
+/// Whether the user confirmed
+///
+/// # Panics
+///
+/// If reading the answer fails unexpectedly
+fn get_confirmation(io: &impl IO) -> bool {
     loop {
         io.show_text("Are you absolutely sure? (y/n):");
         let input = io.get_input().expect("Failed getting delete confirmation");
         match input.to_lowercase().as_str() {
-            "y" | "ye" | "yes" | "ya" | "yuh" | "yarr" | "fuck yeah" => break,
+            "y" | "ye" | "yes" | "ya" | "yuh" | "yarr" | "fuck yeah" => return true,
             "n" | "nu uh" | "no" | "nah" | "hell naw" | "get yo bitchass outta here" => {
-                info!("Exiting. Not deleting note with ID: {id}\n");
+                return false;
+            }
+            _ => warn!("Invalid input. Please enter 'y' or 'n'\n"),
+        }
+    }
+}
+
+/// Prompt for sort order, then display all notes a page at a time
+///
+/// # Parameters
+///
+/// - `io`: Console I/O implementation
+/// - `service`: Note service backend
+fn handle_list(io: &impl IO, service: &NoteService) {
+    let (sort, direction) = get_sort(io);
+    let query = ListQuery {
+        sort,
+        direction,
+        ..ListQuery::default()
+    };
+    show_paged_list(io, service, query);
+}
+
+/// Prompt for a tag, then fetch all notes with it and display them in a table
+///
+/// # Parameters
+///
+/// - `io`: Console I/O implementation
+/// - `service`: Note service backend
+///
+/// # Panics
+///
+/// If reading the tag fails unexpectedly
+fn handle_list_by_tag(io: &impl IO, service: &NoteService) {
+    io.show_title("List notes by tag");
+
+    let tag: String = loop {
+        io.show_text("Tag:");
+        let input = io.get_input().expect("Failed getting tag");
+        match NoteService::validate_tags(std::slice::from_ref(&input), service.max_name_size) {
+            Ok(()) => break input,
+            Err(e) => error!("{e}\n"),
+        }
+    };
+
+    let query = ListQuery {
+        tag: Some(tag),
+        ..ListQuery::default()
+    };
+    show_paged_list(io, service, query);
+}
+
+/// Prompt for a sort key and direction, defaulting to ascending ID on empty input
+///
+/// # Parameters
+///
+/// - `io`: Console I/O implementation
+///
+/// # Panics
+///
+/// If reading the sort order fails unexpectedly
+fn get_sort(io: &impl IO) -> (SortKey, SortDirection) {
+    let sort = loop {
+        io.show_text("Sort by (id/name/owner/created/updated) [id]:");
+        let input = io.get_input().expect("Failed getting sort key");
+        match input.to_lowercase().as_str() {
+            "" | "id" => break SortKey::Id,
+            "name" => break SortKey::Name,
+            "owner" => break SortKey::Owner,
+            "created" => break SortKey::CreatedAt,
+            "updated" => break SortKey::UpdatedAt,
+            _ => warn!("Invalid sort key: {input}\n"),
+        }
+    };
+
+    let direction = loop {
+        io.show_text("Direction (asc/desc) [asc]:");
+        let input = io.get_input().expect("Failed getting sort direction");
+        match input.to_lowercase().as_str() {
+            "" | "asc" => break SortDirection::Ascending,
+            "desc" => break SortDirection::Descending,
+            _ => warn!("Invalid direction: {input}\n"),
+        }
+    };
+
+    (sort, direction)
+}
+
+/// Display the notes matching a query one page at a time, letting the user move between pages
+///
+/// # Parameters
+///
+/// - `io`: Console I/O implementation
+/// - `service`: Note service backend
+/// - `query`: Filter and sort order of the notes. Its offset and limit are managed here
+///
+/// # Panics
+///
+/// If reading the page navigation fails unexpectedly
+fn show_paged_list(io: &impl IO, service: &NoteService, mut query: ListQuery) {
+    query.offset = 0;
+    loop {
+        // Fetch one extra note to know if there is a next page
+        query.limit = Some(PAGE_SIZE + 1);
+        let mut partial_notes: Vec<PartialNote> = match service.list_notes(&query) {
+            Ok(n) => n,
+            Err(e) => {
+                error!("{e}\n");
                 return;
             }
+        };
+        let has_next = partial_notes.len() > PAGE_SIZE;
+        partial_notes.truncate(PAGE_SIZE);
+        io.show_notes_list(partial_notes);
+
+        let has_previous = query.offset > 0;
+        if !has_next && !has_previous {
+            return;
+        }
+
+        loop {
+            io.show_text(&format!(
+                "Page {}. (n)ext, (p)revious or (q)uit:",
+                query.offset / PAGE_SIZE + 1
+            ));
+            let input = io.get_input().expect("Failed getting page navigation");
+            match input.to_lowercase().as_str() {
+                "n" if has_next => {
+                    query.offset += PAGE_SIZE;
+                    break;
+                }
+                "p" if has_previous => {
+                    query.offset -= PAGE_SIZE;
+                    break;
+                }
+                "" | "q" => return,
+                _ => warn!("Invalid input: {input}\n"),
+            }
+        }
+    }
+}
+
+/// Prompt for a search query, then display the matching notes with snippets
+///
+/// # Parameters
+///
+/// - `io`: Console I/O implementation
+/// - `service`: Note service backend
+///
+/// # Panics
+///
+/// If reading the query fails unexpectedly
+fn handle_search(io: &impl IO, service: &NoteService) {
+    io.show_title("Search notes");
+
+    io.show_text("Query:");
+    let query = io.get_input().expect("Failed getting search query");
+
+    match service.search_notes(&query) {
+        Ok(results) if results.is_empty() => info!("No notes matched: {query}\n"),
+        Ok(results) => io.show_search_results(results),
+        Err(e) => error!("{e}\n"),
+    }
+}
+
+/// Prompt for a note ID, list its revisions, then show and optionally restore one of them
+///
+/// # Parameters
+///
+/// - `io`: I/O implementation
+/// - `service`: Note service backend
+///
+/// # Panics
+///
+/// If reading the ID, revision or confirmation fails unexpectedly
+fn handle_history(io: &impl IO, service: &NoteService) {
+    io.show_title("Note history");
+
+    let id: NoteId = loop {
+        io.show_text("ID:");
+        let input = io.get_input().expect("Failed getting note ID");
+        match input.parse::<NoteId>() {
+            Ok(id) => break id,
+            Err(e) => error!("{e}\n"),
+        }
+    };
+
+    match service.list_revisions(id) {
+        Ok(revisions) if revisions.is_empty() => {
+            info!("Note with ID {id} has no previous revisions\n");
+            return;
+        }
+        Ok(revisions) => io.show_revisions_list(revisions),
+        Err(e) => {
+            error!("{e}\n");
+            return;
+        }
+    }
+
+    let revision = loop {
+        io.show_text("Revision to view (leave empty to go back):");
+        let input = io.get_input().expect("Failed getting revision");
+        if input.is_empty() {
+            return;
+        }
+        match input.parse::<u32>() {
+            Ok(revision) => match service.read_revision(id, revision) {
+                Ok(revision) => break revision,
+                Err(e) => error!("{e}\n"),
+            },
+            Err(e) => error!("{e}\n"),
+        }
+    };
+
+    let title_text = format!(
+        "#{}: {} (revision {})",
+        revision.note_id, revision.name, revision.revision
+    )
+    .bold();
+    io.show_text(&"-".repeat(20));
+    io.show_text(&title_text);
+    io.show_text(&format!("Updated: {}", revision.updated_at).dimmed());
+    io.show_text("");
+    io.show_text(&revision.content);
+    io.show_text(&"-".repeat(20));
+    io.show_text("");
+
+    loop {
+        io.show_text("Restore this revision? (y/n):");
+        let input = io.get_input().expect("Failed getting restore confirmation");
+        match input.to_lowercase().as_str() {
+            "y" | "yes" => break,
+            "n" | "no" | "" => return,
             _ => warn!("Invalid input. Please enter 'y' or 'n'\n"),
         }
     }
 
-    match service.delete_note(id) {
-        Ok(()) => info!("Successfully deleted note with ID: {id}\n"),
+    match service.restore_revision(id, revision.revision) {
+        Ok(()) => info!(
+            "Restored revision {} of note with ID: {id}\n",
+            revision.revision
+        ),
         Err(e) => error!("{e}\n"),
     }
 }
 
-/// Fetch all notes and display in a table
+/// List the notes in the trash, then restore or permanently purge one of them
 ///
 /// # Parameters
 ///
-/// - `io`: Console I/O implementation
+/// - `io`: I/O implementation
 /// - `service`: Note service backend
-fn handle_list(io: &impl IO, service: &NoteService) {
-    let partial_notes: Vec<PartialNote> = match service.list_notes() {
-        Ok(n) => n,
+///
+/// # Panics
+///
+/// If reading the action or ID fails unexpectedly
+fn handle_trash(io: &impl IO, service: &NoteService) {
+    io.show_title("Trash");
+
+    match service.list_trash() {
+        Ok(trashed_notes) if trashed_notes.is_empty() => {
+            info!("The trash is empty\n");
+            return;
+        }
+        Ok(trashed_notes) => io.show_trash_list(trashed_notes),
         Err(e) => {
             error!("{e}\n");
             return;
         }
+    }
+
+    let purge = loop {
+        io.show_text("(r)estore, (p)urge permanently or (q)uit:");
+        let input = io.get_input().expect("Failed getting trash action");
+        match input.to_lowercase().as_str() {
+            "r" | "restore" => break false,
+            "p" | "purge" => break true,
+            "" | "q" => return,
+            _ => warn!("Invalid input: {input}\n"),
+        }
     };
-    io.show_notes_list(partial_notes);
+
+    let id: NoteId = loop {
+        io.show_text("ID:");
+        let input = io.get_input().expect("Failed getting note ID");
+        match input.parse::<NoteId>() {
+            Ok(id) => break id,
+            Err(e) => error!("{e}\n"),
+        }
+    };
+
+    if purge {
+        match service.purge_note(id) {
+            Ok(()) => info!("Permanently deleted note with ID: {id}\n"),
+            Err(e) => error!("{e}\n"),
+        }
+    } else {
+        match service.restore_note(id) {
+            Ok(()) => info!("Restored note with ID {id} from the trash\n"),
+            Err(e) => error!("{e}\n"),
+        }
+    }
+}
+
+/// Prompt for a note ID, show who it is shared with, then share it with another user
+///
+/// # Parameters
+///
+/// - `io`: I/O implementation
+/// - `service`: Note service backend
+///
+/// # Panics
+///
+/// If reading the ID, user or permission fails unexpectedly
+fn handle_share(io: &impl IO, service: &NoteService) {
+    io.show_title("Share note");
+
+    let id: NoteId = loop {
+        io.show_text("ID:");
+        let input = io.get_input().expect("Failed getting note ID");
+        match input.parse::<NoteId>() {
+            Ok(id) => break id,
+            Err(e) => error!("{e}\n"),
+        }
+    };
+
+    match service.list_grants(id) {
+        Ok(grants) if grants.is_empty() => {}
+        Ok(grants) => io.show_grants_list(grants),
+        Err(e) => {
+            error!("{e}\n");
+            return;
+        }
+    }
+
+    io.show_text("User to share with:");
+    let user = io.get_input().expect("Failed getting user");
+
+    let permission = loop {
+        io.show_text("Permission (read/write) [read]:");
+        let input = io.get_input().expect("Failed getting permission");
+        match input.to_lowercase().as_str() {
+            "" | "r" | "read" => break Permission::Read,
+            "w" | "write" => break Permission::Write,
+            _ => warn!("Invalid permission: {input}\n"),
+        }
+    };
+
+    match service.share_note(id, &user, permission) {
+        Ok(()) => info!("Gave {user} {permission} access to note with ID: {id}\n"),
+        Err(e) => error!("{e}\n"),
+    }
+}
+
+/// Prompt for a note ID, show who it is shared with, then take away the access of one of them
+///
+/// # Parameters
+///
+/// - `io`: I/O implementation
+/// - `service`: Note service backend
+///
+/// # Panics
+///
+/// If reading the ID or user fails unexpectedly
+fn handle_unshare(io: &impl IO, service: &NoteService) {
+    io.show_title("Unshare note");
+
+    let id: NoteId = loop {
+        io.show_text("ID:");
+        let input = io.get_input().expect("Failed getting note ID");
+        match input.parse::<NoteId>() {
+            Ok(id) => break id,
+            Err(e) => error!("{e}\n"),
+        }
+    };
+
+    match service.list_grants(id) {
+        Ok(grants) if grants.is_empty() => {
+            info!("Note with ID {id} isn't shared with anyone\n");
+            return;
+        }
+        Ok(grants) => io.show_grants_list(grants),
+        Err(e) => {
+            error!("{e}\n");
+            return;
+        }
+    }
+
+    io.show_text("User to unshare with:");
+    let user = io.get_input().expect("Failed getting user");
+
+    match service.unshare_note(id, &user) {
+        Ok(()) => info!("Took away the access of {user} to note with ID: {id}\n"),
+        Err(e) => error!("{e}\n"),
+    }
+}
+
+/// Prompt for the owner of a new note if the user is a member of any groups
+///
+/// # Parameters
+///
+/// - `io`: I/O implementation
+/// - `service`: Note service backend
+///
+/// # Returns
+///
+/// The chosen group, or `None` if the user owns the note themselves
+///
+/// # Panics
+///
+/// If reading the owner fails unexpectedly
+fn get_owner(io: &impl IO, service: &NoteService) -> Option<String> {
+    let groups = service.user_groups();
+    if groups.is_empty() {
+        return None;
+    }
+
+    loop {
+        io.show_text(&format!(
+            "Owner (leave empty for yourself, or one of: {}):",
+            groups.join(", ")
+        ));
+        let input = io.get_input().expect("Failed getting note owner");
+        if input.is_empty() || input == service.user {
+            break None;
+        }
+        if groups.contains(&input) {
+            break Some(input);
+        }
+        warn!("You're not a member of the group: {input}\n");
+    }
+}
+
+/// Prompt for a line of tags until they are valid
+///
+/// # Parameters
+///
+/// - `io`: I/O implementation
+/// - `service`: Note service backend
+///
+/// # Panics
+///
+/// If reading the tags fails unexpectedly
+fn get_tags(io: &impl IO, service: &NoteService) -> Vec<String> {
+    loop {
+        io.show_text("Tags (separated by spaces or commas, leave empty for none):");
+        let input = io.get_input().expect("Failed getting note tags");
+        let tags = NoteService::parse_tags(&input);
+        match NoteService::validate_tags(&tags, service.max_name_size) {
+            Ok(()) => {
+                trace!("Got valid tags: {tags:?}\n");
+                break tags;
+            }
+            Err(e) => error!("Got invalid tags: {e}\n"),
+        }
+    }
 }
 
 /// Create a note containing the flag via service